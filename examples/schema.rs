@@ -3,7 +3,17 @@ use std::fs::create_dir_all;
 
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 
-use tic_tac_toe::msg::{ExecuteMsg, GetCountResponse, InstantiateMsg, QueryMsg};
+use tic_tac_toe::msg::{
+    AreEquivalentResponse, BoardDiffResponse, BoardStatesResponse, CanonicalBoardResponse,
+    CapabilitiesResponse, ControlResponse, DistanceToWinResponse, ExecuteMsg,
+    ExpectedSymbolResponse, ExplainMoveResponse, GetBoardResponse, GetPackedBoardResponse,
+    GetStateResponse, GetStateWithMovesResponse, HasForkMoveResponse, InstantiateMsg,
+    IsMirrorGameResponse, IsWinningMoveResponse, LineStatusResponse, MigrateMsg,
+    MoveSequenceResponse, MovesByOutcomeResponse, MyResultResponse, NonForkingMovesResponse,
+    OptimalResultResponse, PositionDifficultyResponse, PositionalMetricsResponse, QueryMsg,
+    RenderBoardResponse, TacticalSummaryResponse, ThreatCountResponse, TimeUsageResponse,
+    WinningContinuationsResponse,
+};
 use tic_tac_toe::state::State;
 
 fn main() {
@@ -15,6 +25,35 @@ fn main() {
     export_schema(&schema_for!(InstantiateMsg), &out_dir);
     export_schema(&schema_for!(ExecuteMsg), &out_dir);
     export_schema(&schema_for!(QueryMsg), &out_dir);
+    export_schema(&schema_for!(MigrateMsg), &out_dir);
     export_schema(&schema_for!(State), &out_dir);
-    export_schema(&schema_for!(GetCountResponse), &out_dir);
+    export_schema(&schema_for!(GetStateResponse), &out_dir);
+    export_schema(&schema_for!(RenderBoardResponse), &out_dir);
+    export_schema(&schema_for!(MoveSequenceResponse), &out_dir);
+    export_schema(&schema_for!(ExpectedSymbolResponse), &out_dir);
+    export_schema(&schema_for!(WinningContinuationsResponse), &out_dir);
+    export_schema(&schema_for!(ControlResponse), &out_dir);
+    export_schema(&schema_for!(MyResultResponse), &out_dir);
+    export_schema(&schema_for!(IsWinningMoveResponse), &out_dir);
+    export_schema(&schema_for!(TimeUsageResponse), &out_dir);
+    export_schema(&schema_for!(PositionalMetricsResponse), &out_dir);
+    export_schema(&schema_for!(DistanceToWinResponse), &out_dir);
+    export_schema(&schema_for!(GetBoardResponse), &out_dir);
+    export_schema(&schema_for!(NonForkingMovesResponse), &out_dir);
+    export_schema(&schema_for!(CapabilitiesResponse), &out_dir);
+    export_schema(&schema_for!(ThreatCountResponse), &out_dir);
+    export_schema(&schema_for!(AreEquivalentResponse), &out_dir);
+    export_schema(&schema_for!(BoardStatesResponse), &out_dir);
+    export_schema(&schema_for!(OptimalResultResponse), &out_dir);
+    export_schema(&schema_for!(MovesByOutcomeResponse), &out_dir);
+    export_schema(&schema_for!(IsMirrorGameResponse), &out_dir);
+    export_schema(&schema_for!(CanonicalBoardResponse), &out_dir);
+    export_schema(&schema_for!(GetStateWithMovesResponse), &out_dir);
+    export_schema(&schema_for!(ExplainMoveResponse), &out_dir);
+    export_schema(&schema_for!(TacticalSummaryResponse), &out_dir);
+    export_schema(&schema_for!(BoardDiffResponse), &out_dir);
+    export_schema(&schema_for!(LineStatusResponse), &out_dir);
+    export_schema(&schema_for!(HasForkMoveResponse), &out_dir);
+    export_schema(&schema_for!(PositionDifficultyResponse), &out_dir);
+    export_schema(&schema_for!(GetPackedBoardResponse), &out_dir);
 }
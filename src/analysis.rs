@@ -0,0 +1,404 @@
+use crate::state::{pack_board, GridCell};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Three cells that win the game when one symbol fills them.
+pub type Line = [(usize, usize); 3];
+
+/// Every line of three cells that wins the classic game: rows, columns and both diagonals.
+pub const LINES: [Line; 8] = [
+    [(0, 0), (0, 1), (0, 2)],
+    [(1, 0), (1, 1), (1, 2)],
+    [(2, 0), (2, 1), (2, 2)],
+    [(0, 0), (1, 0), (2, 0)],
+    [(0, 1), (1, 1), (2, 1)],
+    [(0, 2), (1, 2), (2, 2)],
+    [(0, 0), (1, 1), (2, 2)],
+    [(0, 2), (1, 1), (2, 0)],
+];
+
+/// Diagonals that wrap around the board edges, computed modulo the board size. Rows and
+/// columns already span the full width, so wrapping them adds no new lines.
+pub fn wrapped_diagonals() -> Vec<Line> {
+    (0..3)
+        .flat_map(|start| {
+            [
+                [(0, start), (1, (start + 1) % 3), (2, (start + 2) % 3)],
+                [(0, start), (1, (start + 2) % 3), (2, (start + 1) % 3)],
+            ]
+        })
+        .filter(|line| !LINES.contains(line))
+        .collect()
+}
+
+/// The lines that win under the game's rules: the classic eight, plus the wrapped diagonals
+/// when `toroidal` is set. Every analysis below takes such a set, so all of them agree on
+/// which lines count.
+pub fn lines_for(toroidal: bool) -> Vec<Line> {
+    let mut lines = LINES.to_vec();
+    if toroidal {
+        lines.extend(wrapped_diagonals());
+    }
+    lines
+}
+
+/// Returns the first completed classic line on the board, if any.
+pub fn winning_line(board: &[[GridCell; 3]; 3]) -> Option<Line> {
+    winning_line_in(board, &LINES)
+}
+
+/// Like `winning_line`, also counting wrapped diagonals when `toroidal` is set.
+pub fn winning_line_for(board: &[[GridCell; 3]; 3], toroidal: bool) -> Option<Line> {
+    winning_line_in(board, &lines_for(toroidal))
+}
+
+/// The first line of `lines` that one symbol has completed.
+pub fn winning_line_in(board: &[[GridCell; 3]; 3], lines: &[Line]) -> Option<Line> {
+    lines.iter().copied().find(|line| {
+        let [(r0, c0), (r1, c1), (r2, c2)] = *line;
+        board[r0][c0] != GridCell::Empty
+            && board[r0][c0] == board[r1][c1]
+            && board[r1][c1] == board[r2][c2]
+    })
+}
+
+/// Derives the symbol that should be placed next purely from board parity: X moves first,
+/// so X is due whenever both symbols have been placed equally often.
+pub fn expected_symbol(board: &[[GridCell; 3]; 3]) -> GridCell {
+    let count = |symbol| {
+        board
+            .iter()
+            .flatten()
+            .filter(|cell| **cell == symbol)
+            .count()
+    };
+    if count(GridCell::X) > count(GridCell::O) {
+        GridCell::O
+    } else {
+        GridCell::X
+    }
+}
+
+/// Value of a position under perfect play, from the point of view of the side it is scored for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Outcome {
+    Loss,
+    Draw,
+    Win,
+}
+
+impl Outcome {
+    /// The same result seen from the opponent's side.
+    pub fn flip(self) -> Outcome {
+        match self {
+            Outcome::Loss => Outcome::Win,
+            Outcome::Draw => Outcome::Draw,
+            Outcome::Win => Outcome::Loss,
+        }
+    }
+}
+
+pub fn opponent(symbol: GridCell) -> GridCell {
+    match symbol {
+        GridCell::X => GridCell::O,
+        GridCell::O => GridCell::X,
+        GridCell::Empty => GridCell::Empty,
+    }
+}
+
+/// True once the board has a completed line or no empty cell left.
+pub fn is_over(board: &[[GridCell; 3]; 3], lines: &[Line]) -> bool {
+    winning_line_in(board, lines).is_some() || empty_cells(board).is_empty()
+}
+
+pub fn empty_cells(board: &[[GridCell; 3]; 3]) -> Vec<(usize, usize)> {
+    (0..3)
+        .flat_map(|row| (0..3).map(move |col| (row, col)))
+        .filter(|(row, col)| board[*row][*col] == GridCell::Empty)
+        .collect()
+}
+
+/// Cells the side to move may still play; empty once the game is over.
+pub fn legal_moves(board: &[[GridCell; 3]; 3], lines: &[Line]) -> Vec<(usize, usize)> {
+    if winning_line_in(board, lines).is_some() {
+        return vec![];
+    }
+    empty_cells(board)
+}
+
+/// Copy of the board with `symbol` placed at `(row, col)`.
+pub fn play(
+    board: &[[GridCell; 3]; 3],
+    row: usize,
+    col: usize,
+    symbol: GridCell,
+) -> [[GridCell; 3]; 3] {
+    let mut next = *board;
+    next[row][col] = symbol;
+    next
+}
+
+/// Minimax value of the position for `to_move`, who is about to play.
+pub fn solve(board: &[[GridCell; 3]; 3], to_move: GridCell, lines: &[Line]) -> Outcome {
+    Solver::new(lines).solve(board, to_move)
+}
+
+/// Minimax search that remembers every position it has valued. Boards that are rotations or
+/// reflections of each other share an entry, so a search from the empty board values at most
+/// the 765 essentially different positions instead of walking the full game tree. The wrapped
+/// diagonals map onto each other under the same symmetries, so this holds on a torus too.
+pub struct Solver {
+    lines: Vec<Line>,
+    memo: BTreeMap<u32, Outcome>,
+}
+
+impl Solver {
+    /// A solver scoring wins on `lines`, with an empty table.
+    pub fn new(lines: &[Line]) -> Solver {
+        Solver {
+            lines: lines.to_vec(),
+            memo: BTreeMap::new(),
+        }
+    }
+
+    /// Minimax value of the position for `to_move`, who is about to play.
+    pub fn solve(&mut self, board: &[[GridCell; 3]; 3], to_move: GridCell) -> Outcome {
+        if winning_line_in(board, &self.lines).is_some() {
+            // whoever completed the line moved last
+            return Outcome::Loss;
+        }
+        // the packed board uses the low 18 bits; the side to move goes above them
+        let key = pack_board(&canonical(board).0) | ((to_move == GridCell::O) as u32) << 18;
+        if let Some(outcome) = self.memo.get(&key) {
+            return *outcome;
+        }
+
+        let mut best = None;
+        for (row, col) in empty_cells(board) {
+            let outcome = self
+                .solve(&play(board, row, col, to_move), opponent(to_move))
+                .flip();
+            best = best.max(Some(outcome));
+            if outcome == Outcome::Win {
+                break;
+            }
+        }
+        let best = best.unwrap_or(Outcome::Draw);
+        self.memo.insert(key, best);
+        best
+    }
+
+    /// How many distinct positions have been valued so far.
+    pub fn positions(&self) -> usize {
+        self.memo.len()
+    }
+}
+
+/// Whether `symbol` playing `(row, col)` worsens their perfect-play result. Both searches run
+/// on `solver`, so the second is answered almost entirely from the first one's table.
+pub fn is_blunder(
+    solver: &mut Solver,
+    board: &[[GridCell; 3]; 3],
+    row: usize,
+    col: usize,
+    symbol: GridCell,
+) -> bool {
+    let before = solver.solve(board, symbol);
+    let after = solver.solve(&play(board, row, col, symbol), opponent(symbol));
+    after.flip() < before
+}
+
+/// Moves for `player` that keep a forced win in hand. Empty unless it is `player`'s turn.
+pub fn winning_continuations(
+    board: &[[GridCell; 3]; 3],
+    player: GridCell,
+    lines: &[Line],
+) -> Vec<(usize, usize)> {
+    if expected_symbol(board) != player {
+        return vec![];
+    }
+    let mut solver = Solver::new(lines);
+    legal_moves(board, lines)
+        .into_iter()
+        .filter(|(row, col)| {
+            solver.solve(&play(board, *row, *col, player), opponent(player)) == Outcome::Loss
+        })
+        .collect()
+}
+
+/// Cells currently holding `symbol`, in row-major order.
+pub fn cells_of(board: &[[GridCell; 3]; 3], symbol: GridCell) -> Vec<(usize, usize)> {
+    (0..3)
+        .flat_map(|row| (0..3).map(move |col| (row, col)))
+        .filter(|(row, col)| board[*row][*col] == symbol)
+        .collect()
+}
+
+/// Lines that hold at least one X and one O, so neither side can complete them any more.
+pub fn contested_lines(board: &[[GridCell; 3]; 3], lines: &[Line]) -> Vec<Line> {
+    lines
+        .iter()
+        .copied()
+        .filter(|line| {
+            let has = |symbol| line.iter().any(|(row, col)| board[*row][*col] == symbol);
+            has(GridCell::X) && has(GridCell::O)
+        })
+        .collect()
+}
+
+/// Where a cell sits on the board, for the classic centre/corner/edge heuristics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CellKind {
+    Center,
+    Corner,
+    Edge,
+}
+
+pub fn cell_kind(row: usize, col: usize) -> CellKind {
+    match (row, col) {
+        (1, 1) => CellKind::Center,
+        (0, 0) | (0, 2) | (2, 0) | (2, 2) => CellKind::Corner,
+        _ => CellKind::Edge,
+    }
+}
+
+/// Fewest further `symbol`s needed to complete a line the opponent has not entered yet,
+/// or `None` when every line is blocked.
+pub fn distance_to_win(board: &[[GridCell; 3]; 3], symbol: GridCell, lines: &[Line]) -> Option<u8> {
+    lines
+        .iter()
+        .filter(|line| {
+            line.iter()
+                .all(|(row, col)| board[*row][*col] != opponent(symbol))
+        })
+        .map(|line| {
+            line.iter()
+                .filter(|(row, col)| board[*row][*col] == GridCell::Empty)
+                .count() as u8
+        })
+        .min()
+}
+
+/// Number of lines where `symbol` holds two cells and the third is still empty.
+pub fn threat_count(board: &[[GridCell; 3]; 3], symbol: GridCell, lines: &[Line]) -> usize {
+    lines
+        .iter()
+        .filter(|line| {
+            let count = |cell| {
+                line.iter()
+                    .filter(|(row, col)| board[*row][*col] == cell)
+                    .count()
+            };
+            count(symbol) == 2 && count(GridCell::Empty) == 1
+        })
+        .count()
+}
+
+/// Whether `symbol`, about to move, can create two threats at once while the opponent has
+/// no immediate win to answer with.
+pub fn can_fork(board: &[[GridCell; 3]; 3], symbol: GridCell, lines: &[Line]) -> bool {
+    legal_moves(board, lines).into_iter().any(|(row, col)| {
+        let next = play(board, row, col, symbol);
+        threat_count(&next, symbol, lines) >= 2 && threat_count(&next, opponent(symbol), lines) == 0
+    })
+}
+
+/// Moves for `player` after which the opponent cannot fork. Empty unless it is `player`'s turn.
+pub fn non_forking_moves(
+    board: &[[GridCell; 3]; 3],
+    player: GridCell,
+    lines: &[Line],
+) -> Vec<(usize, usize)> {
+    if expected_symbol(board) != player {
+        return vec![];
+    }
+    legal_moves(board, lines)
+        .into_iter()
+        .filter(|(row, col)| !can_fork(&play(board, *row, *col, player), opponent(player), lines))
+        .collect()
+}
+
+/// How hard the position is for the side to move, from 0 to 100: the share of legal moves
+/// that give away some of what perfect play would keep. A lone saving move among many
+/// losing ones rates close to 100; 0 when every move is as good as the best, or none is left.
+pub fn position_difficulty(board: &[[GridCell; 3]; 3], lines: &[Line]) -> u8 {
+    let to_move = expected_symbol(board);
+    let mut solver = Solver::new(lines);
+    let outcomes: Vec<Outcome> = legal_moves(board, lines)
+        .into_iter()
+        .map(|(row, col)| {
+            solver
+                .solve(&play(board, row, col, to_move), opponent(to_move))
+                .flip()
+        })
+        .collect();
+    let best = match outcomes.iter().max() {
+        Some(best) => *best,
+        None => return 0,
+    };
+    let mistakes = outcomes.iter().filter(|outcome| **outcome < best).count();
+    (mistakes * 100 / outcomes.len()) as u8
+}
+
+/// The eight symmetries of the square board.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Transform {
+    Identity,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    FlipHorizontal,
+    FlipVertical,
+    FlipDiagonal,
+    FlipAntiDiagonal,
+}
+
+impl Transform {
+    pub const ALL: [Transform; 8] = [
+        Transform::Identity,
+        Transform::Rotate90,
+        Transform::Rotate180,
+        Transform::Rotate270,
+        Transform::FlipHorizontal,
+        Transform::FlipVertical,
+        Transform::FlipDiagonal,
+        Transform::FlipAntiDiagonal,
+    ];
+
+    /// Where the cell at `(row, col)` lands. Rotations are clockwise.
+    pub fn map(self, row: usize, col: usize) -> (usize, usize) {
+        match self {
+            Transform::Identity => (row, col),
+            Transform::Rotate90 => (col, 2 - row),
+            Transform::Rotate180 => (2 - row, 2 - col),
+            Transform::Rotate270 => (2 - col, row),
+            Transform::FlipHorizontal => (row, 2 - col),
+            Transform::FlipVertical => (2 - row, col),
+            Transform::FlipDiagonal => (col, row),
+            Transform::FlipAntiDiagonal => (2 - col, 2 - row),
+        }
+    }
+
+    pub fn apply(self, board: &[[GridCell; 3]; 3]) -> [[GridCell; 3]; 3] {
+        let mut out = [[GridCell::Empty; 3]; 3];
+        for (row, cells) in board.iter().enumerate() {
+            for (col, cell) in cells.iter().enumerate() {
+                let (to_row, to_col) = self.map(row, col);
+                out[to_row][to_col] = *cell;
+            }
+        }
+        out
+    }
+}
+
+/// The lexicographically smallest image of `board` under the square's symmetries, together
+/// with the first transform that produces it.
+pub fn canonical(board: &[[GridCell; 3]; 3]) -> ([[GridCell; 3]; 3], Transform) {
+    Transform::ALL
+        .iter()
+        .map(|transform| (transform.apply(board), *transform))
+        .min_by_key(|(image, _)| *image)
+        .unwrap_or((*board, Transform::Identity))
+}
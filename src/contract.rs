@@ -1,14 +1,37 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
-use cosmwasm_std::{to_binary, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdResult, Addr};
-use cw2::set_contract_version;
+use cosmwasm_std::{
+    to_binary, Addr, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdError, StdResult,
+    Timestamp,
+};
+use cw2::{get_contract_version, set_contract_version};
 
+use crate::analysis::{
+    can_fork, canonical, cell_kind, cells_of, contested_lines, distance_to_win, empty_cells,
+    expected_symbol, is_blunder, is_over, legal_moves, lines_for, non_forking_moves, opponent,
+    play, position_difficulty, solve, threat_count, winning_continuations, winning_line_for,
+    CellKind, Line, Outcome, Solver, Transform,
+};
 use crate::error::ContractError;
-use crate::msg::{ExecuteMsg, GetStateResponse, InstantiateMsg, QueryMsg};
-use crate::state::{GridCell, State, STATE, Turn};
+use crate::msg::{
+    AreEquivalentResponse, BoardDiffResponse, BoardStatesResponse, CanonicalBoardResponse,
+    CapabilitiesResponse, CellChange, ControlResponse, Coord, DistanceToWinResponse, ExecuteMsg,
+    ExpectedSymbolResponse, ExplainMoveResponse, GameResult, GetBoardResponse,
+    GetPackedBoardResponse, GetStateResponse, GetStateWithMovesResponse, HasForkMoveResponse,
+    InstantiateMsg, IsMirrorGameResponse, IsWinningMoveResponse, LineInfo, LineState,
+    LineStatusResponse, MigrateMsg, MoveReason, MoveSequenceResponse, MovesByOutcomeResponse,
+    MyResultResponse, NonForkingMovesResponse, OptimalResult, OptimalResultResponse, PlayerControl,
+    PositionDifficultyResponse, PositionalCounts, PositionalMetricsResponse, QueryMsg,
+    RenderBoardResponse, TacticalSummaryResponse, ThreatCountResponse, TimeUsageResponse,
+    WinningContinuationsResponse, EXECUTE_CAPABILITIES, QUERY_CAPABILITIES,
+};
+use crate::state::{
+    pack_board, GameConfig, GridCell, LegacyState, MoveRecord, StagedMove, State, Turn, CONFIG,
+    LEGACY_STATE, REFEREE, STATE,
+};
 use rand::prelude::*;
 
-/**
+/*
  * Tic Tac Toe contract
  * A game can only contains 2 players. The first player to reach 3 in a row, or 3 in a column, or 3 in a diagonal, wins.
  *
@@ -38,151 +61,684 @@ const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
+    // There is no wager to hold, so anything attached could never be paid back out
+    if !info.funds.is_empty() {
+        return Err(ContractError::UnexpectedFunds {});
+    }
+
+    // In referee mode the sender officiates and must not hold either seat
+    let players = match &msg.player0 {
+        Some(player0) => {
+            if *player0 == msg.opponent || *player0 == info.sender || msg.opponent == info.sender {
+                return Err(ContractError::InvalidPlayer {});
+            }
+            [player0.clone(), msg.opponent.clone()]
+        }
+        None => [info.sender.clone(), msg.opponent.clone()],
+    };
+
     let state = State {
-        players: [info.sender.clone(), msg.opponent.clone()],
+        players,
         board: [[GridCell::Empty; 3]; 3],
         next_turn: Turn::Player0,
         winner: None,
+        history: vec![],
+        nonce: 0,
+        created_at: env.block.time,
+        staged_move: None,
+        lives_left: [msg.config.lives; 2],
     };
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
     STATE.save(deps.storage, &state)?;
+    CONFIG.save(deps.storage, &msg.config)?;
 
-    Ok(Response::new()
+    let mut res = Response::new()
         .add_attribute("method", "instantiate")
         .add_attribute("owner", info.sender.to_string())
         .add_attribute("opponent", msg.opponent.to_string())
-       .add_attribute("turn", state.next_turn.to_string()))
+        .add_attribute("turn", state.next_turn.to_string());
+    if msg.player0.is_some() {
+        REFEREE.save(deps.storage, &info.sender)?;
+        res = res.add_attribute("referee", info.sender.to_string());
+    }
+    Ok(res)
+}
+
+/// Migrate is only open to the contract admin set on chain, so a replacement state is
+/// trusted to come from governance, but it must still describe a reachable game between the
+/// same players, and a finished game keeps its result.
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+    let version = get_contract_version(deps.storage)?;
+    if version.contract != CONTRACT_NAME {
+        return Err(ContractError::WrongContract {
+            contract: version.contract,
+        });
+    }
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    // Games instantiated before the rule variants existed play the classic rules
+    let config = match CONFIG.may_load(deps.storage)? {
+        Some(config) => config,
+        None => {
+            let config = GameConfig::default();
+            CONFIG.save(deps.storage, &config)?;
+            config
+        }
+    };
+
+    // A stored state that only parses in the old layout comes from the first release
+    let legacy = LEGACY_STATE.may_load(deps.storage).ok().flatten();
+    let mut res = Response::new().add_attribute("method", "migrate");
+    match msg.state {
+        None => {
+            if let Some(legacy) = legacy {
+                STATE.save(deps.storage, &upgrade_legacy_state(legacy, env.block.time)?)?;
+                res = res.add_attribute("upgraded", "true");
+            }
+        }
+        Some(state) => {
+            // A stored game that no longer loads is what a backup is for, so only a readable
+            // one can refuse it
+            let stored = match legacy {
+                Some(legacy) => upgrade_legacy_state(legacy, env.block.time).ok(),
+                None => STATE.load(deps.storage).ok(),
+            };
+            let invalid = |msg: &str| ContractError::InvalidState {
+                msg: msg.to_string(),
+            };
+            match stored {
+                Some(stored) if stored.next_turn == Turn::Ended => {
+                    return Err(invalid("A finished game cannot be restored over"));
+                }
+                Some(stored) if stored.players != state.players => {
+                    return Err(invalid("Players do not match the stored game"));
+                }
+                None if state.players[0] == state.players[1] => {
+                    return Err(invalid("Players must be two different addresses"));
+                }
+                _ => {}
+            }
+            validate_state(&state, &config)?;
+            STATE.save(deps.storage, &state)?;
+            res = res.add_attribute("restored", "true");
+        }
+    }
+    Ok(res)
+}
+
+/// Converts a first-release state: the symbols are swapped so player0 holds X, and the winner
+/// and turn are worked out again from the board. The first release kept no history or creation
+/// time, so the history starts empty and the game counts as created at `now`.
+fn upgrade_legacy_state(legacy: LegacyState, now: Timestamp) -> Result<State, ContractError> {
+    let mut board = legacy.board;
+    for cell in board.iter_mut().flatten() {
+        if *cell != GridCell::Empty {
+            *cell = opponent(*cell);
+        }
+    }
+
+    // Moves could overwrite cells back then, which can leave a board no alternating game reaches
+    let count = |symbol| cells_of(&board, symbol).len();
+    let (x, o) = (count(GridCell::X), count(GridCell::O));
+    if x != o && x != o + 1 {
+        return Err(ContractError::InvalidState {
+            msg: "The stored game cannot be converted, restore it from a backup".to_string(),
+        });
+    }
+
+    let winner = check_winner(&board, &legacy.players, false);
+    let next_turn = if winner.is_some() || empty_cells(&board).is_empty() {
+        Turn::Ended
+    } else if x == o {
+        Turn::Player0
+    } else {
+        Turn::Player1
+    };
+    Ok(State {
+        players: legacy.players,
+        board,
+        next_turn,
+        winner,
+        history: vec![],
+        nonce: 0,
+        created_at: now,
+        staged_move: None,
+        lives_left: [0; 2],
+    })
+}
+
+/// Checks that `state` is one the contract could have produced itself: the history replays
+/// onto the board, and the winner, turn and staged move all agree with it.
+fn validate_state(state: &State, config: &GameConfig) -> Result<(), ContractError> {
+    let invalid = |msg: &str| {
+        Err(ContractError::InvalidState {
+            msg: msg.to_string(),
+        })
+    };
+
+    let mut board = [[GridCell::Empty; 3]; 3];
+    let mut symbol = GridCell::X;
+    for m in &state.history {
+        if m.symbol != symbol || m.row > 2 || m.col > 2 {
+            return invalid("History is not a sequence of alternating moves");
+        }
+        if winning_line_for(&board, config.toroidal).is_some() {
+            return invalid("History continues after the game was won");
+        }
+        let cell = &mut board[m.row as usize][m.col as usize];
+        if *cell != GridCell::Empty {
+            return invalid("History plays the same cell twice");
+        }
+        *cell = symbol;
+        symbol = opponent(symbol);
+    }
+    if board != state.board {
+        return invalid("Board does not match history");
+    }
+    if state.nonce < state.history.len() as u64 {
+        return invalid("Nonce is behind the number of moves played");
+    }
+
+    if state.lives_left.iter().any(|lives| *lives > config.lives) {
+        return invalid("More lives left than the game started with");
+    }
+    let mut winner = check_winner(&board, &state.players, config.toroidal);
+    // in the lives variant a game also ends once a player has no lives left
+    if config.lives > 0 {
+        if let Some(index) = state.lives_left.iter().position(|lives| *lives == 0) {
+            winner = Some(state.players[1 - index].clone());
+        }
+    }
+    if state.winner != winner {
+        return invalid("Winner does not match board");
+    }
+    let next_turn = if winner.is_some() || empty_cells(&board).is_empty() {
+        Turn::Ended
+    } else if symbol == GridCell::X {
+        Turn::Player0
+    } else {
+        Turn::Player1
+    };
+    if state.next_turn != next_turn {
+        return invalid("Next turn does not match board");
+    }
+
+    if let Some(staged) = &state.staged_move {
+        if !config.confirm_moves {
+            return invalid("Staged move in a game without move confirmation");
+        }
+        let open = staged.row <= 2
+            && staged.col <= 2
+            && board[staged.row as usize][staged.col as usize] == GridCell::Empty;
+        if next_turn == Turn::Ended || !open {
+            return invalid("Staged move is not playable");
+        }
+    }
+    Ok(())
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn execute(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
     match msg {
-        ExecuteMsg::Move {row, col} => try_move(deps, info, row, col),
-        ExecuteMsg::MoveRandom { } => try_move_random(deps, info),
+        ExecuteMsg::Move {
+            row,
+            col,
+            expected_nonce,
+        } => try_move(deps, env, info, row, col, expected_nonce),
+        ExecuteMsg::MoveRandom { expected_nonce } => {
+            try_move_random(deps, env, info, expected_nonce)
+        }
+        ExecuteMsg::MoveAlgebraic {
+            square,
+            expected_nonce,
+        } => try_move_algebraic(deps, env, info, square, expected_nonce),
+        ExecuteMsg::StageMove {
+            row,
+            col,
+            expected_nonce,
+        } => try_stage_move(deps, info, row, col, expected_nonce),
+        ExecuteMsg::ConfirmMove { expected_nonce } => {
+            try_confirm_move(deps, env, info, expected_nonce)
+        }
+        ExecuteMsg::CancelMove {} => try_cancel_move(deps, info),
+        ExecuteMsg::Reset {} => try_reset(deps, env, info),
     }
 }
 
-pub fn try_move_random(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+pub fn try_move_algebraic(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    square: String,
+    expected_nonce: Option<u64>,
+) -> Result<Response, ContractError> {
+    let (row, col) = parse_square(&square).ok_or(ContractError::InvalidSquare { square })?;
+    try_move(deps, env, info, row, col, expected_nonce)
+}
+
+pub fn try_move_random(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    expected_nonce: Option<u64>,
+) -> Result<Response, ContractError> {
     let state = STATE.load(deps.storage)?;
     if state.players[0] != info.sender {
         return Err(ContractError::InvalidPlayer {});
     }
+    // Only empty cells are playable, so pick among those
     let mut rng = thread_rng();
-    let row = rng.gen_range(0..3);
-    let col = rng.gen_range(0..3);
-    try_move(deps, info, row, col)
+    let (row, col) =
+        *empty_cells(&state.board)
+            .choose(&mut rng)
+            .ok_or(ContractError::InvalidMove {
+                msg: "The game has already ended".to_string(),
+            })?;
+    try_move(deps, env, info, row as u8, col as u8, expected_nonce)
 }
 
-pub fn try_move(deps: DepsMut, info: MessageInfo, row: u8, col: u8) -> Result<Response, ContractError> {
-    // check if the row and col are valid
-    if ( row > 2) || (col > 2) {
+pub fn try_move(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    row: u8,
+    col: u8,
+    expected_nonce: Option<u64>,
+) -> Result<Response, ContractError> {
+    let state = STATE.load(deps.storage)?;
+    let config = CONFIG.load(deps.storage)?;
+    let symbol = validate_move(&state, &info.sender, row, col, expected_nonce)?;
+    if config.confirm_moves {
         return Err(ContractError::InvalidMove {
-            msg: "Row and col must be between 1 and 3".to_string(),
+            msg: "Moves must be staged and confirmed in this game".to_string(),
         });
     }
 
-    // Check if the player is eligible to play
-    let state = STATE.load(deps.storage)?;
-    if !state.players.contains(&info.sender) {
+    // Every check has passed; nothing above touched the state
+    let lives_before = state.lives_left;
+    let state = apply_move(state, &config, symbol, row, col, env.block.time);
+    STATE.save(deps.storage, &state)?;
+
+    let res = Response::new()
+        .add_attribute("method", "try_move")
+        .add_attribute("nonce", state.nonce.to_string());
+    let res = with_life_lost_attribute(res, lives_before, &state);
+    Ok(with_board_attribute(res, &config, &state.board))
+}
+
+pub fn try_stage_move(
+    deps: DepsMut,
+    info: MessageInfo,
+    row: u8,
+    col: u8,
+    expected_nonce: Option<u64>,
+) -> Result<Response, ContractError> {
+    let mut state = STATE.load(deps.storage)?;
+    let config = CONFIG.load(deps.storage)?;
+    require_confirm_moves(&config)?;
+    validate_move(&state, &info.sender, row, col, expected_nonce)?;
+    if state.staged_move.is_some() {
         return Err(ContractError::InvalidMove {
-            msg: "You are not allowed to play".to_string(),
+            msg: "A move is already staged".to_string(),
         });
     }
 
-    // Check the player's turn is valid. Player0 = X, Player1 = O. PlayerO is the first player = contract owner
-    match state.next_turn {
-        Turn::Player0 => {
-            if info.sender != state.players[0] {
-                return Err(ContractError::InvalidMove {
-                    msg: "It's not your turn".to_string(),
-                });
-            }
-        },
-        Turn::Player1 => {
-            if info.sender != state.players[1] {
-                return Err(ContractError::InvalidMove {
-                    msg: "It's not your turn".to_string(),
-                });
-            }
-        },
-        Turn::Ended => {
-            return Err(ContractError::InvalidMove {
-                msg: "The game has already ended".to_string()
-            });
-        }
-    }
+    state.staged_move = Some(StagedMove { row, col });
+    state.nonce += 1;
+    STATE.save(deps.storage, &state)?;
 
-    STATE.update(deps.storage, |mut state| -> Result<_, ContractError> {
-        state.next_turn = match state.next_turn {
-            Turn::Player0 => Turn::Player1,
-            Turn::Player1 => Turn::Player0,
-            Turn::Ended => return Err(ContractError::InvalidMove {
-                msg: "The game has already ended".to_string(),
-            }),
-        };
+    Ok(Response::new()
+        .add_attribute("method", "try_stage_move")
+        .add_attribute("nonce", state.nonce.to_string()))
+}
 
-        state.board[row as usize][col as usize] = match state.next_turn {
-            Turn::Player0 => GridCell::X,
-            Turn::Player1 => GridCell::O,
-            Turn::Ended => return Err(ContractError::InvalidMove {
-                msg: "The game has already ended".to_string(),
-            }),
-        };
+pub fn try_confirm_move(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    expected_nonce: Option<u64>,
+) -> Result<Response, ContractError> {
+    let mut state = STATE.load(deps.storage)?;
+    let config = CONFIG.load(deps.storage)?;
+    require_confirm_moves(&config)?;
+    let staged = state.staged_move.take().ok_or(ContractError::InvalidMove {
+        msg: "No move is staged".to_string(),
+    })?;
+    let symbol = validate_move(&state, &info.sender, staged.row, staged.col, expected_nonce)?;
+
+    let lives_before = state.lives_left;
+    let state = apply_move(
+        state,
+        &config,
+        symbol,
+        staged.row,
+        staged.col,
+        env.block.time,
+    );
+    STATE.save(deps.storage, &state)?;
+
+    let res = Response::new()
+        .add_attribute("method", "try_confirm_move")
+        .add_attribute("nonce", state.nonce.to_string());
+    let res = with_life_lost_attribute(res, lives_before, &state);
+    Ok(with_board_attribute(res, &config, &state.board))
+}
 
-        state.winner = check_winner(&state.board, &state.players);
-        Ok(state)
+pub fn try_cancel_move(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    let mut state = STATE.load(deps.storage)?;
+    require_confirm_moves(&CONFIG.load(deps.storage)?)?;
+    let staged = state.staged_move.take().ok_or(ContractError::InvalidMove {
+        msg: "No move is staged".to_string(),
     })?;
+    // Only the player who staged the move, i.e. the one on turn, may withdraw it
+    validate_move(&state, &info.sender, staged.row, staged.col, None)?;
+
+    state.nonce += 1;
+    STATE.save(deps.storage, &state)?;
 
-    Ok(Response::new().add_attribute("method", "try_move"))
+    Ok(Response::new()
+        .add_attribute("method", "try_cancel_move")
+        .add_attribute("nonce", state.nonce.to_string()))
 }
 
-pub fn grid_to_addr_index(grid: GridCell) -> usize {
-    match grid {
-        GridCell::Empty => 0,
-        GridCell::X => 1,
-        GridCell::O => 2,
+/// Lets the referee void the game in progress. A finished game keeps its result, as it does
+/// everywhere else.
+pub fn try_reset(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
+    if REFEREE.may_load(deps.storage)?.as_ref() != Some(&info.sender) {
+        return Err(ContractError::Unauthorized {});
+    }
+    let mut state = STATE.load(deps.storage)?;
+    if state.next_turn == Turn::Ended {
+        return Err(ContractError::InvalidMove {
+            msg: "The game has already ended".to_string(),
+        });
+    }
+
+    let config = CONFIG.load(deps.storage)?;
+    state.reset_board(env.block.time);
+    state.lives_left = [config.lives; 2];
+    STATE.save(deps.storage, &state)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "try_reset")
+        .add_attribute("nonce", state.nonce.to_string()))
+}
+
+/// Staging, confirming and cancelling only exist in games that opted into confirmation.
+fn require_confirm_moves(config: &GameConfig) -> Result<(), ContractError> {
+    if !config.confirm_moves {
+        return Err(ContractError::InvalidMove {
+            msg: "Move confirmation is not enabled for this game".to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Names the player who lost a life on this move, so event consumers can tell the board
+/// was reset (or the game lost) by a blunder.
+fn with_life_lost_attribute(res: Response, lives_before: [u8; 2], state: &State) -> Response {
+    match (0..2).find(|&index| state.lives_left[index] < lives_before[index]) {
+        Some(index) => res.add_attribute("life_lost", state.players[index].to_string()),
+        None => res,
+    }
+}
+
+/// Appends the board as a single `board` attribute when the game opted into it.
+fn with_board_attribute(
+    res: Response,
+    config: &GameConfig,
+    board: &[[GridCell; 3]; 3],
+) -> Response {
+    if config.emit_full_board {
+        res.add_attribute("board", board_string(board))
+    } else {
+        res
     }
 }
 
-pub fn check_winner(board: &[[GridCell; 3]; 3], players: &[Addr; 2]) -> Option<Addr> {
-    // check rows
-    for row in 0..3 {
-        if board[row][0] == board[row][1] && board[row][1] == board[row][2] && board[row][0] != GridCell::Empty {
-            let index = grid_to_addr_index(board[row][0]);
-            return Some(players[index].clone());
+/// The board as nine characters, row by row, with `-` for an empty cell.
+pub fn board_string(board: &[[GridCell; 3]; 3]) -> String {
+    board
+        .iter()
+        .flatten()
+        .map(|cell| match cell {
+            GridCell::Empty => '-',
+            GridCell::X => 'X',
+            GridCell::O => 'O',
+        })
+        .collect()
+}
+
+/// Checks that `sender` may play `(row, col)` right now and returns the symbol they would
+/// place. Never modifies `state`.
+fn validate_move(
+    state: &State,
+    sender: &Addr,
+    row: u8,
+    col: u8,
+    expected_nonce: Option<u64>,
+) -> Result<GridCell, ContractError> {
+    // Reject submissions made against an outdated view of the game
+    if let Some(expected) = expected_nonce {
+        if expected != state.nonce {
+            return Err(ContractError::StaleNonce {
+                expected,
+                current: state.nonce,
+            });
         }
     }
 
-    // check columns
-    for col in 0..3 {
-        if board[0][col] == board[1][col] && board[1][col] == board[2][col] && board[0][col] != GridCell::Empty {
-            let index = grid_to_addr_index(board[0][col]);
-            return Some(players[index].clone());
+    let msg = match move_reason(state, sender, row, col) {
+        MoveReason::Ok => return Ok(player_symbol(state, sender)?),
+        MoveReason::OutOfBounds => "Row and col must be between 1 and 3",
+        MoveReason::NotAPlayer => "You are not allowed to play",
+        MoveReason::GameEnded => "The game has already ended",
+        MoveReason::NotYourTurn => "It's not your turn",
+        MoveReason::Occupied => "Cell is already occupied",
+        MoveReason::MoveStaged => "A move is already staged",
+    };
+    Err(ContractError::InvalidMove {
+        msg: msg.to_string(),
+    })
+}
+
+/// Why `sender` may or may not play `(row, col)`, checked in the same order as a real move.
+fn move_reason(state: &State, sender: &Addr, row: u8, col: u8) -> MoveReason {
+    // check if the row and col are valid
+    if (row > 2) || (col > 2) {
+        return MoveReason::OutOfBounds;
+    }
+
+    // Check if the player is eligible to play
+    if !state.players.contains(sender) {
+        return MoveReason::NotAPlayer;
+    }
+
+    // Check the player's turn is valid. Player0 = X, Player1 = O. PlayerO is the first player = contract owner
+    let mover = match state.next_turn {
+        Turn::Player0 => &state.players[0],
+        Turn::Player1 => &state.players[1],
+        Turn::Ended => return MoveReason::GameEnded,
+    };
+    if sender != mover {
+        return MoveReason::NotYourTurn;
+    }
+
+    if state.board[row as usize][col as usize] != GridCell::Empty {
+        return MoveReason::Occupied;
+    }
+
+    MoveReason::Ok
+}
+
+/// Places an already validated move and advances the game. This is a pure transform of
+/// `state`; the caller is responsible for having validated the move and for saving the result.
+pub fn apply_move(
+    mut state: State,
+    config: &GameConfig,
+    symbol: GridCell,
+    row: u8,
+    col: u8,
+    time: Timestamp,
+) -> State {
+    // In the lives variant, a move that worsens the mover's perfect-play result is a blunder
+    let blunder = config.lives > 0
+        && is_blunder(
+            &mut Solver::new(&lines_for(config.toroidal)),
+            &state.board,
+            row as usize,
+            col as usize,
+            symbol,
+        );
+
+    state.board[row as usize][col as usize] = symbol;
+    state.history.push(MoveRecord {
+        symbol,
+        row,
+        col,
+        time,
+    });
+
+    // A completed line or a full board ends the game; no winner means a draw
+    state.winner = check_winner(&state.board, &state.players, config.toroidal);
+    state.next_turn = if state.winner.is_some() || empty_cells(&state.board).is_empty() {
+        Turn::Ended
+    } else if symbol == GridCell::X {
+        Turn::Player1
+    } else {
+        Turn::Player0
+    };
+    state.nonce += 1;
+
+    if blunder {
+        // a blunder costs a life and a fresh board; losing the last life loses the game
+        let index = grid_to_addr_index(symbol).unwrap_or(0);
+        state.lives_left[index] = state.lives_left[index].saturating_sub(1);
+        if state.lives_left[index] == 0 {
+            state.winner = Some(state.players[1 - index].clone());
+            state.next_turn = Turn::Ended;
+        } else {
+            state.reset_board(time);
         }
     }
+    state
+}
+
+/// Maps a token to the index of the player who places it. Player0 plays X, Player1 plays O.
+pub fn grid_to_addr_index(grid: GridCell) -> Option<usize> {
+    match grid {
+        GridCell::Empty => None,
+        GridCell::X => Some(0),
+        GridCell::O => Some(1),
+    }
+}
+
+pub fn check_winner(
+    board: &[[GridCell; 3]; 3],
+    players: &[Addr; 2],
+    toroidal: bool,
+) -> Option<Addr> {
+    let (row, col) = winning_line_for(board, toroidal)?[0];
+    grid_to_addr_index(board[row][col]).map(|index| players[index].clone())
+}
 
-    // check diagonals
-    if board[0][0] == board[1][1] && board[1][1] == board[2][2] && board[0][0] != GridCell::Empty {
-        let index = grid_to_addr_index(board[0][0]);
-        return Some(players[index].clone());
+/// Renders the board as ASCII art, bracketing the cells of the winning line if there is one.
+pub fn render_board_highlighted(board: &[[GridCell; 3]; 3], toroidal: bool) -> String {
+    let line = winning_line_for(board, toroidal);
+    board
+        .iter()
+        .enumerate()
+        .map(|(row, cells)| {
+            cells
+                .iter()
+                .enumerate()
+                .map(|(col, cell)| match line {
+                    Some(line) if line.contains(&(row, col)) => format!("[{}]", cell),
+                    _ => format!(" {} ", cell),
+                })
+                .collect::<Vec<_>>()
+                .join("|")
+        })
+        .collect::<Vec<_>>()
+        .join("\n---+---+---\n")
+}
+
+/// Parses a square such as "b2" into (row, col). Files a-c name the columns left to right and
+/// ranks 1-3 count rows from the bottom, as on a chessboard.
+pub fn parse_square(square: &str) -> Option<(u8, u8)> {
+    match square.as_bytes() {
+        [file @ b'a'..=b'c', rank @ b'1'..=b'3'] => Some((b'3' - rank, file - b'a')),
+        _ => None,
     }
+}
 
-    return None;
+/// Renders the board as ASCII art with rank numbers down the side and file letters underneath.
+pub fn render_board_algebraic(board: &[[GridCell; 3]; 3]) -> String {
+    let rows = board
+        .iter()
+        .enumerate()
+        .map(|(row, cells)| {
+            let cells = cells
+                .iter()
+                .map(|cell| format!(" {} ", cell))
+                .collect::<Vec<_>>();
+            format!("{} {}", 3 - row, cells.join("|"))
+        })
+        .collect::<Vec<_>>();
+    format!("{}\n   a   b   c", rows.join("\n  ---+---+---\n"))
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::GetState {} => to_binary(&query_state(deps)?),
+        QueryMsg::RenderBoardHighlighted {} => to_binary(&query_render_board_highlighted(deps)?),
+        QueryMsg::MoveSequence {} => to_binary(&query_move_sequence(deps)?),
+        QueryMsg::ExpectedSymbol {} => to_binary(&query_expected_symbol(deps)?),
+        QueryMsg::WinningContinuations { player } => {
+            to_binary(&query_winning_continuations(deps, player)?)
+        }
+        QueryMsg::Control {} => to_binary(&query_control(deps)?),
+        QueryMsg::MyResult { player } => to_binary(&query_my_result(deps, player)?),
+        QueryMsg::IsWinningMove { player, row, col } => {
+            to_binary(&query_is_winning_move(deps, player, row, col)?)
+        }
+        QueryMsg::GetTimeUsage {} => to_binary(&query_time_usage(deps)?),
+        QueryMsg::PositionalMetrics {} => to_binary(&query_positional_metrics(deps)?),
+        QueryMsg::DistanceToWin {} => to_binary(&query_distance_to_win(deps)?),
+        QueryMsg::GetBoard { viewer } => to_binary(&query_board(deps, viewer)?),
+        QueryMsg::NonForkingMoves { player } => to_binary(&query_non_forking_moves(deps, player)?),
+        QueryMsg::RenderAlgebraic {} => to_binary(&query_render_algebraic(deps)?),
+        QueryMsg::Capabilities {} => to_binary(&query_capabilities()),
+        QueryMsg::ThreatCount {} => to_binary(&query_threat_count(deps)?),
+        QueryMsg::AreEquivalent { board_a, board_b } => {
+            to_binary(&query_are_equivalent(board_a, board_b))
+        }
+        QueryMsg::BoardStates {} => to_binary(&query_board_states(deps)?),
+        QueryMsg::OptimalResult { player } => to_binary(&query_optimal_result(deps, player)?),
+        QueryMsg::MovesByOutcome {} => to_binary(&query_moves_by_outcome(deps)?),
+        QueryMsg::IsMirrorGame {} => to_binary(&query_is_mirror_game(deps)?),
+        QueryMsg::CanonicalBoard {} => to_binary(&query_canonical_board(deps)?),
+        QueryMsg::GetStateWithMoves {} => to_binary(&query_state_with_moves(deps)?),
+        QueryMsg::ExplainMove { player, row, col } => {
+            to_binary(&query_explain_move(deps, player, row, col)?)
+        }
+        QueryMsg::TacticalSummary {} => to_binary(&query_tactical_summary(deps)?),
+        QueryMsg::BoardDiff { from, to } => to_binary(&query_board_diff(deps, from, to)?),
+        QueryMsg::LineStatus {} => to_binary(&query_line_status(deps)?),
+        QueryMsg::HasForkMove { player } => to_binary(&query_has_fork_move(deps, player)?),
+        QueryMsg::PositionDifficulty {} => to_binary(&query_position_difficulty(deps)?),
+        QueryMsg::GetPackedBoard {} => to_binary(&query_packed_board(deps)?),
     }
 }
 
@@ -191,65 +747,2048 @@ fn query_state(deps: Deps) -> StdResult<GetStateResponse> {
     Ok(GetStateResponse { state })
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
-    use cosmwasm_std::{coins, from_binary};
-    use rand::prelude::*;
+fn query_render_board_highlighted(deps: Deps) -> StdResult<RenderBoardResponse> {
+    let state = STATE.load(deps.storage)?;
+    let config = CONFIG.load(deps.storage)?;
+    Ok(RenderBoardResponse {
+        board: render_board_highlighted(&state.board, config.toroidal),
+    })
+}
 
-    #[test]
-    fn proper_initialization() {
-        let mut deps = mock_dependencies();
+fn query_render_algebraic(deps: Deps) -> StdResult<RenderBoardResponse> {
+    let state = STATE.load(deps.storage)?;
+    Ok(RenderBoardResponse {
+        board: render_board_algebraic(&state.board),
+    })
+}
 
-        let msg = InstantiateMsg { opponent: Addr::unchecked("player1") };
-        let info = mock_info("player0", &coins(1000, "earth"));
+fn query_capabilities() -> CapabilitiesResponse {
+    let names = |list: &[&str]| list.iter().map(|name| name.to_string()).collect();
+    CapabilitiesResponse {
+        execute: names(EXECUTE_CAPABILITIES),
+        query: names(QUERY_CAPABILITIES),
+    }
+}
 
-        // we can just call .unwrap() to assert this was a success
-        let res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
-        assert_eq!(0, res.messages.len());
+fn query_move_sequence(deps: Deps) -> StdResult<MoveSequenceResponse> {
+    let state = STATE.load(deps.storage)?;
+    let sequence = state
+        .history
+        .iter()
+        .map(|m| format!("{}@{},{}", m.symbol, m.row, m.col))
+        .collect::<Vec<_>>()
+        .join(" ");
+    Ok(MoveSequenceResponse { sequence })
+}
 
-        // it worked, let's query the state
-        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetState {}).unwrap();
-        let state: GetStateResponse = from_binary(&res).unwrap();
-        assert_eq!(Addr::unchecked("player1"), state.state.players[1]);
+fn query_expected_symbol(deps: Deps) -> StdResult<ExpectedSymbolResponse> {
+    let state = STATE.load(deps.storage)?;
+    Ok(ExpectedSymbolResponse {
+        symbol: expected_symbol(&state.board),
+    })
+}
+
+fn query_winning_continuations(
+    deps: Deps,
+    player: Addr,
+) -> StdResult<WinningContinuationsResponse> {
+    let state = STATE.load(deps.storage)?;
+    let symbol = player_symbol(&state, &player)?;
+    if state.next_turn == Turn::Ended {
+        return Ok(WinningContinuationsResponse { moves: vec![] });
     }
+    let moves = winning_continuations(&state.board, symbol, &rule_lines(deps)?)
+        .into_iter()
+        .map(Coord::from)
+        .collect();
+    Ok(WinningContinuationsResponse { moves })
+}
 
-    #[test]
-    fn test_move() {
-        let mut deps = mock_dependencies();
+fn query_control(deps: Deps) -> StdResult<ControlResponse> {
+    let state = STATE.load(deps.storage)?;
+    let control = |symbol| {
+        let cells: Vec<Coord> = cells_of(&state.board, symbol)
+            .into_iter()
+            .map(Coord::from)
+            .collect();
+        PlayerControl {
+            count: cells.len() as u32,
+            cells,
+        }
+    };
+    let contested_lines = contested_lines(&state.board, &rule_lines(deps)?)
+        .into_iter()
+        .map(|line| line.map(Coord::from))
+        .collect();
+    Ok(ControlResponse {
+        x: control(GridCell::X),
+        o: control(GridCell::O),
+        contested_lines,
+    })
+}
 
-        let msg = InstantiateMsg { opponent: Addr::unchecked("player1") };
-        let info = mock_info("player0", &coins(2, "token"));
-        let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+fn query_my_result(deps: Deps, player: Addr) -> StdResult<MyResultResponse> {
+    let state = STATE.load(deps.storage)?;
+    let result = if !state.players.contains(&player) {
+        GameResult::NotAPlayer
+    } else if let Some(winner) = state.winner {
+        if winner == player {
+            GameResult::Won
+        } else {
+            GameResult::Lost
+        }
+    } else if is_over(&state.board, &rule_lines(deps)?) {
+        GameResult::Drew
+    } else {
+        GameResult::InProgress
+    };
+    Ok(MyResultResponse { result })
+}
 
-        // beneficiary can release it
-        let info = mock_info("player0", &coins(2, "token"));
-        let msg = ExecuteMsg::Move { row: 0, col: 0 };
-        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+fn query_is_winning_move(
+    deps: Deps,
+    player: Addr,
+    row: u8,
+    col: u8,
+) -> StdResult<IsWinningMoveResponse> {
+    let state = STATE.load(deps.storage)?;
+    let symbol = player_symbol(&state, &player)?;
+    if row > 2 || col > 2 {
+        return Err(StdError::generic_err("Cell is out of bounds"));
+    }
+    let (row, col) = (row as usize, col as usize);
+    if state.board[row][col] != GridCell::Empty {
+        return Err(StdError::generic_err("Cell is already occupied"));
+    }
 
-        // should increase counter by 1
-        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetState {}).unwrap();
-        let state: GetStateResponse = from_binary(&res).unwrap();
-        assert_eq!(GridCell::O, state.state.board[0][0]);
+    let config = CONFIG.load(deps.storage)?;
+    let board = play(&state.board, row, col, symbol);
+    let winner = check_winner(&board, &state.players, config.toroidal);
+    Ok(IsWinningMoveResponse {
+        winning: state.winner.is_none() && winner == Some(player),
+    })
+}
+
+/// Sums, per player, the time between the previous move (or game creation) and each of
+/// their own moves.
+fn query_time_usage(deps: Deps) -> StdResult<TimeUsageResponse> {
+    let state = STATE.load(deps.storage)?;
+    let mut seconds = [0u64; 2];
+    let mut clock = state.created_at;
+    for record in &state.history {
+        if let Some(index) = grid_to_addr_index(record.symbol) {
+            seconds[index] += record.time.seconds().saturating_sub(clock.seconds());
+        }
+        clock = record.time;
     }
+    Ok(TimeUsageResponse {
+        player0_seconds: seconds[0],
+        player1_seconds: seconds[1],
+    })
+}
 
-    #[test]
-    fn test_move_random() {
-        let mut deps = mock_dependencies();
+fn query_positional_metrics(deps: Deps) -> StdResult<PositionalMetricsResponse> {
+    let state = STATE.load(deps.storage)?;
+    let counts = |symbol| {
+        let mut counts = PositionalCounts::default();
+        for (row, col) in cells_of(&state.board, symbol) {
+            match cell_kind(row, col) {
+                CellKind::Center => counts.center += 1,
+                CellKind::Corner => counts.corners += 1,
+                CellKind::Edge => counts.edges += 1,
+            }
+        }
+        counts
+    };
+    Ok(PositionalMetricsResponse {
+        x: counts(GridCell::X),
+        o: counts(GridCell::O),
+    })
+}
 
-        let msg = InstantiateMsg { opponent: Addr::unchecked("player1") };
-        let info = mock_info("player0", &coins(2, "token"));
-        let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+fn query_distance_to_win(deps: Deps) -> StdResult<DistanceToWinResponse> {
+    let state = STATE.load(deps.storage)?;
+    let lines = rule_lines(deps)?;
+    Ok(DistanceToWinResponse {
+        x: distance_to_win(&state.board, GridCell::X, &lines),
+        o: distance_to_win(&state.board, GridCell::O, &lines),
+    })
+}
 
-        // beneficiary can release it
-        let info = mock_info("player0", &coins(2, "token"));
-        let msg = ExecuteMsg::MoveRandom { };
-        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+fn query_board(deps: Deps, viewer: Addr) -> StdResult<GetBoardResponse> {
+    let state = STATE.load(deps.storage)?;
+    let config = CONFIG.load(deps.storage)?;
+    let mut board = state.board;
 
-        // should increase counter by 1
-        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetState {}).unwrap();
-        let state: GetStateResponse = from_binary(&res).unwrap();
-        assert_eq!(GridCell::O, state.state.board[0][0]);
+    // Under fog the latest move stays hidden from everyone but its author until the game ends
+    if config.fog && state.next_turn != Turn::Ended {
+        if let Some(last) = state.history.last() {
+            let author = grid_to_addr_index(last.symbol).map(|index| &state.players[index]);
+            if author != Some(&viewer) {
+                board[last.row as usize][last.col as usize] = GridCell::Empty;
+            }
+        }
+    }
+    Ok(GetBoardResponse { board })
+}
+
+fn query_non_forking_moves(deps: Deps, player: Addr) -> StdResult<NonForkingMovesResponse> {
+    let state = STATE.load(deps.storage)?;
+    let symbol = player_symbol(&state, &player)?;
+    if state.next_turn == Turn::Ended {
+        return Ok(NonForkingMovesResponse { moves: vec![] });
+    }
+    let moves = non_forking_moves(&state.board, symbol, &rule_lines(deps)?)
+        .into_iter()
+        .map(Coord::from)
+        .collect();
+    Ok(NonForkingMovesResponse { moves })
+}
+
+fn query_threat_count(deps: Deps) -> StdResult<ThreatCountResponse> {
+    let state = STATE.load(deps.storage)?;
+    let lines = rule_lines(deps)?;
+    Ok(ThreatCountResponse {
+        x: threat_count(&state.board, GridCell::X, &lines) as u32,
+        o: threat_count(&state.board, GridCell::O, &lines) as u32,
+    })
+}
+
+fn query_are_equivalent(
+    board_a: [[GridCell; 3]; 3],
+    board_b: [[GridCell; 3]; 3],
+) -> AreEquivalentResponse {
+    AreEquivalentResponse {
+        equivalent: canonical(&board_a).0 == canonical(&board_b).0,
+    }
+}
+
+fn query_board_states(deps: Deps) -> StdResult<BoardStatesResponse> {
+    let state = STATE.load(deps.storage)?;
+    let mut board = [[GridCell::Empty; 3]; 3];
+    let mut boards = Vec::with_capacity(state.history.len() + 1);
+    boards.push(board);
+    for m in &state.history {
+        board[m.row as usize][m.col as usize] = m.symbol;
+        boards.push(board);
+    }
+    Ok(BoardStatesResponse { boards })
+}
+
+fn query_optimal_result(deps: Deps, player: Addr) -> StdResult<OptimalResultResponse> {
+    let state = STATE.load(deps.storage)?;
+    let symbol = player_symbol(&state, &player)?;
+    // A game can end with play left on the board, e.g. on a lost last life, so take its result
+    if state.next_turn == Turn::Ended {
+        let result = match state.winner {
+            Some(winner) if winner == player => OptimalResult::Wins,
+            Some(_) => OptimalResult::Loses,
+            None => OptimalResult::Draws,
+        };
+        return Ok(OptimalResultResponse { result });
+    }
+    let to_move = expected_symbol(&state.board);
+    let mut outcome = solve(&state.board, to_move, &rule_lines(deps)?);
+    if symbol != to_move {
+        outcome = outcome.flip();
+    }
+    let result = match outcome {
+        Outcome::Win => OptimalResult::Wins,
+        Outcome::Draw => OptimalResult::Draws,
+        Outcome::Loss => OptimalResult::Loses,
+    };
+    Ok(OptimalResultResponse { result })
+}
+
+/// Each move lands in the first category it fits, so a move that both wins and blocks
+/// is reported as winning.
+fn query_moves_by_outcome(deps: Deps) -> StdResult<MovesByOutcomeResponse> {
+    let state = STATE.load(deps.storage)?;
+    let config = CONFIG.load(deps.storage)?;
+    let mut res = MovesByOutcomeResponse {
+        winning: vec![],
+        blocking: vec![],
+        other: vec![],
+    };
+    let symbol = match state.next_turn {
+        Turn::Player0 => GridCell::X,
+        Turn::Player1 => GridCell::O,
+        Turn::Ended => return Ok(res),
+    };
+
+    let completes = |symbol, row, col| {
+        winning_line_for(&play(&state.board, row, col, symbol), config.toroidal).is_some()
+    };
+    for (row, col) in empty_cells(&state.board) {
+        let list = if completes(symbol, row, col) {
+            &mut res.winning
+        } else if completes(opponent(symbol), row, col) {
+            &mut res.blocking
+        } else {
+            &mut res.other
+        };
+        list.push((row, col).into());
+    }
+    Ok(res)
+}
+
+/// A game is only reported as a mirror once O has replied at least once.
+fn query_is_mirror_game(deps: Deps) -> StdResult<IsMirrorGameResponse> {
+    let state = STATE.load(deps.storage)?;
+    let cells: Vec<_> = state
+        .history
+        .iter()
+        .map(|m| (m.row as usize, m.col as usize))
+        .collect();
+    let mirror = cells.len() >= 2
+        && cells
+            .chunks_exact(2)
+            .all(|pair| Transform::Rotate180.map(pair[0].0, pair[0].1) == pair[1]);
+    Ok(IsMirrorGameResponse { mirror })
+}
+
+fn query_canonical_board(deps: Deps) -> StdResult<CanonicalBoardResponse> {
+    let state = STATE.load(deps.storage)?;
+    let (board, transform) = canonical(&state.board);
+    Ok(CanonicalBoardResponse { board, transform })
+}
+
+fn query_state_with_moves(deps: Deps) -> StdResult<GetStateWithMovesResponse> {
+    let state = STATE.load(deps.storage)?;
+    // next_turn already accounts for wins on wrapped diagonals
+    let moves = if state.next_turn == Turn::Ended {
+        vec![]
+    } else {
+        empty_cells(&state.board)
+            .into_iter()
+            .map(Coord::from)
+            .collect()
+    };
+    Ok(GetStateWithMovesResponse { state, moves })
+}
+
+fn query_explain_move(
+    deps: Deps,
+    player: Addr,
+    row: u8,
+    col: u8,
+) -> StdResult<ExplainMoveResponse> {
+    let state = STATE.load(deps.storage)?;
+    let config = CONFIG.load(deps.storage)?;
+    // With confirm_moves the move goes through StageMove, which also needs nothing staged yet
+    let reason = match move_reason(&state, &player, row, col) {
+        MoveReason::Ok if config.confirm_moves && state.staged_move.is_some() => {
+            MoveReason::MoveStaged
+        }
+        reason => reason,
+    };
+    Ok(ExplainMoveResponse { reason })
+}
+
+/// `must_block` is only set when there is no immediate win to play instead, and
+/// `facing_fork` when the opponent already threatens two lines at once.
+fn query_tactical_summary(deps: Deps) -> StdResult<TacticalSummaryResponse> {
+    let state = STATE.load(deps.storage)?;
+    let symbol = match state.next_turn {
+        Turn::Player0 => GridCell::X,
+        Turn::Player1 => GridCell::O,
+        Turn::Ended => return Ok(TacticalSummaryResponse::default()),
+    };
+    let board = &state.board;
+    let lines = rule_lines(deps)?;
+    let can_win = threat_count(board, symbol, &lines) > 0;
+    let threats = threat_count(board, opponent(symbol), &lines);
+    Ok(TacticalSummaryResponse {
+        can_win,
+        must_block: !can_win && threats > 0,
+        can_fork: can_fork(board, symbol, &lines),
+        facing_fork: !can_win && threats >= 2,
+    })
+}
+
+/// Marks are never removed, so the cells that changed are exactly the moves in between.
+fn query_board_diff(deps: Deps, from: u32, to: u32) -> StdResult<BoardDiffResponse> {
+    let state = STATE.load(deps.storage)?;
+    let (from, to) = (from as usize, to as usize);
+    if from > to || to > state.history.len() {
+        return Err(StdError::generic_err(format!(
+            "Move range must satisfy from <= to <= {}",
+            state.history.len()
+        )));
+    }
+    let changes = state.history[from..to]
+        .iter()
+        .map(|m| CellChange {
+            row: m.row,
+            col: m.col,
+            symbol: m.symbol,
+        })
+        .collect();
+    Ok(BoardDiffResponse { changes })
+}
+
+fn query_line_status(deps: Deps) -> StdResult<LineStatusResponse> {
+    let state = STATE.load(deps.storage)?;
+    let board = &state.board;
+    let lines = rule_lines(deps)?
+        .iter()
+        .map(|line| {
+            let count = |symbol| {
+                line.iter()
+                    .filter(|(row, col)| board[*row][*col] == symbol)
+                    .count()
+            };
+            let state = match (count(GridCell::X), count(GridCell::O)) {
+                (3, _) | (_, 3) => LineState::Completed,
+                (0, 0) => LineState::Open,
+                (_, 0) => LineState::OpenForX,
+                (0, _) => LineState::OpenForO,
+                _ => LineState::Blocked,
+            };
+            LineInfo {
+                cells: line.iter().map(|cell| Coord::from(*cell)).collect(),
+                state,
+            }
+        })
+        .collect();
+    Ok(LineStatusResponse { lines })
+}
+
+/// Unlike `can_fork`, this does not ask whether the opponent could win first; it only
+/// looks for a move that leaves two open threats.
+fn query_has_fork_move(deps: Deps, player: Addr) -> StdResult<HasForkMoveResponse> {
+    let state = STATE.load(deps.storage)?;
+    let symbol = player_symbol(&state, &player)?;
+    let on_turn = match state.next_turn {
+        Turn::Player0 => symbol == GridCell::X,
+        Turn::Player1 => symbol == GridCell::O,
+        Turn::Ended => false,
+    };
+    let lines = rule_lines(deps)?;
+    let fork = on_turn
+        && legal_moves(&state.board, &lines)
+            .into_iter()
+            .any(|(row, col)| {
+                threat_count(&play(&state.board, row, col, symbol), symbol, &lines) >= 2
+            });
+    Ok(HasForkMoveResponse { fork })
+}
+
+fn query_position_difficulty(deps: Deps) -> StdResult<PositionDifficultyResponse> {
+    let state = STATE.load(deps.storage)?;
+    let difficulty = if state.next_turn == Turn::Ended {
+        0
+    } else {
+        position_difficulty(&state.board, &rule_lines(deps)?)
+    };
+    Ok(PositionDifficultyResponse { difficulty })
+}
+
+fn query_packed_board(deps: Deps) -> StdResult<GetPackedBoardResponse> {
+    let state = STATE.load(deps.storage)?;
+    Ok(GetPackedBoardResponse {
+        packed: pack_board(&state.board),
+    })
+}
+
+/// The lines that win in this game, wrapped diagonals included on a toroidal board.
+fn rule_lines(deps: Deps) -> StdResult<Vec<Line>> {
+    Ok(lines_for(CONFIG.load(deps.storage)?.toroidal))
+}
+
+/// The symbol placed by `player`, or an error if they are not seated in this game.
+fn player_symbol(state: &State, player: &Addr) -> StdResult<GridCell> {
+    if *player == state.players[0] {
+        Ok(GridCell::X)
+    } else if *player == state.players[1] {
+        Ok(GridCell::O)
+    } else {
+        Err(StdError::generic_err("Not a player in this game"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::winning_line;
+    use crate::state::unpack_board;
+    use cosmwasm_std::testing::{
+        mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage,
+    };
+    use cosmwasm_std::{coins, from_binary, OwnedDeps, Storage};
+    use schemars::schema::{RootSchema, Schema};
+    use schemars::schema_for;
+    use serde::de::DeserializeOwned;
+
+    #[test]
+    fn proper_initialization() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            opponent: Addr::unchecked("player1"),
+            config: GameConfig::default(),
+            player0: None,
+        };
+        let info = mock_info("player0", &[]);
+
+        // we can just call .unwrap() to assert this was a success
+        let res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        // it worked, let's query the state
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetState {}).unwrap();
+        let state: GetStateResponse = from_binary(&res).unwrap();
+        assert_eq!(Addr::unchecked("player1"), state.state.players[1]);
+    }
+
+    #[test]
+    fn test_referee_mode() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            opponent: Addr::unchecked("player1"),
+            config: GameConfig::default(),
+            player0: Some(Addr::unchecked("player0")),
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("referee", &[]), msg).unwrap();
+        let run = |deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>, sender, msg| {
+            execute(deps.as_mut(), mock_env(), mock_info(sender, &[]), msg)
+        };
+        let play = |row, col| ExecuteMsg::Move {
+            row,
+            col,
+            expected_nonce: None,
+        };
+
+        // the referee holds no seat, so their move is refused
+        let err = run(&mut deps, "referee", play(1, 1)).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::InvalidMove { msg } if msg == "You are not allowed to play"
+        ));
+        run(&mut deps, "player0", play(1, 1)).unwrap();
+
+        // only the referee may reset, and the game starts over
+        let err = run(&mut deps, "player1", ExecuteMsg::Reset {}).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        run(&mut deps, "referee", ExecuteMsg::Reset {}).unwrap();
+        let res: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        assert_eq!([[GridCell::Empty; 3]; 3], res.state.board);
+        assert_eq!(Turn::Player0, res.state.next_turn);
+        assert!(res.state.history.is_empty());
+        assert_eq!(
+            [Addr::unchecked("player0"), Addr::unchecked("player1")],
+            res.state.players
+        );
+
+        // the referee cannot also take a seat
+        let msg = InstantiateMsg {
+            opponent: Addr::unchecked("player1"),
+            config: GameConfig::default(),
+            player0: Some(Addr::unchecked("referee")),
+        };
+        let err = instantiate(deps.as_mut(), mock_env(), mock_info("referee", &[]), msg);
+        assert!(matches!(err, Err(ContractError::InvalidPlayer {})));
+
+        // and a game without a referee has nobody who may reset it
+        let mut deps = setup_game(&[("player0", 1, 1)]);
+        let err = run(&mut deps, "player0", ExecuteMsg::Reset {}).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+    }
+
+    #[test]
+    fn instantiate_rejects_funds() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            opponent: Addr::unchecked("player1"),
+            config: GameConfig::default(),
+            player0: None,
+        };
+        let info = mock_info("player0", &coins(1000, "earth"));
+        let err = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert!(matches!(err, ContractError::UnexpectedFunds {}));
+    }
+
+    #[test]
+    fn test_move() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            opponent: Addr::unchecked("player1"),
+            config: GameConfig::default(),
+            player0: None,
+        };
+        let info = mock_info("player0", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // beneficiary can release it
+        let info = mock_info("player0", &coins(2, "token"));
+        let msg = ExecuteMsg::Move {
+            row: 0,
+            col: 0,
+            expected_nonce: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // should increase counter by 1
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetState {}).unwrap();
+        let state: GetStateResponse = from_binary(&res).unwrap();
+        assert_eq!(GridCell::X, state.state.board[0][0]);
+    }
+
+    #[test]
+    fn test_move_random() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            opponent: Addr::unchecked("player1"),
+            config: GameConfig::default(),
+            player0: None,
+        };
+        let info = mock_info("player0", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // beneficiary can release it
+        let info = mock_info("player0", &coins(2, "token"));
+        let msg = ExecuteMsg::MoveRandom {
+            expected_nonce: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // should increase counter by 1
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetState {}).unwrap();
+        let state: GetStateResponse = from_binary(&res).unwrap();
+        let placed = state
+            .state
+            .board
+            .iter()
+            .flatten()
+            .filter(|cell| **cell == GridCell::X)
+            .count();
+        assert_eq!(1, placed);
+    }
+
+    #[test]
+    fn test_winning_lines() {
+        // the anti-diagonal used to be missed
+        let o = GridCell::O;
+        let e = GridCell::Empty;
+        let board = [[e, e, o], [e, o, e], [o, e, e]];
+        assert_eq!(Some([(0, 2), (1, 1), (2, 0)]), winning_line(&board));
+        assert_eq!(Some(1), grid_to_addr_index(o));
+        assert_eq!(None, grid_to_addr_index(e));
+    }
+
+    #[test]
+    fn test_render_board_highlighted() {
+        // X takes the main diagonal while O plays the top edge
+        let deps = setup_game(&[
+            ("player0", 0, 0),
+            ("player1", 0, 1),
+            ("player0", 1, 1),
+            ("player1", 0, 2),
+            ("player0", 2, 2),
+        ]);
+
+        let rendered: RenderBoardResponse =
+            query_json(deps.as_ref(), QueryMsg::RenderBoardHighlighted {});
+        assert_eq!(
+            "[X]| O | O \n---+---+---\n   |[X]|   \n---+---+---\n   |   |[X]",
+            rendered.board
+        );
+
+        let state: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        assert_eq!(Some(Addr::unchecked("player0")), state.state.winner);
+        assert_eq!(Turn::Ended, state.state.next_turn);
+    }
+
+    #[test]
+    fn test_move_sequence() {
+        let deps = setup_game(&[("player0", 0, 0), ("player1", 1, 1), ("player0", 2, 2)]);
+
+        let res: MoveSequenceResponse = query_json(deps.as_ref(), QueryMsg::MoveSequence {});
+        assert_eq!("X@0,0 O@1,1 X@2,2", res.sequence);
+    }
+
+    #[test]
+    fn test_expected_symbol() {
+        let mut deps = setup_game(&[]);
+        let res: ExpectedSymbolResponse = query_json(deps.as_ref(), QueryMsg::ExpectedSymbol {});
+        assert_eq!(GridCell::X, res.symbol);
+
+        for (player, row, col, expected) in [
+            ("player0", 0, 0, GridCell::O),
+            ("player1", 1, 1, GridCell::X),
+            ("player0", 2, 2, GridCell::O),
+        ] {
+            let msg = ExecuteMsg::Move {
+                row,
+                col,
+                expected_nonce: None,
+            };
+            execute(deps.as_mut(), mock_env(), mock_info(player, &[]), msg).unwrap();
+            let res: ExpectedSymbolResponse =
+                query_json(deps.as_ref(), QueryMsg::ExpectedSymbol {});
+            assert_eq!(expected, res.symbol);
+        }
+    }
+
+    #[test]
+    fn test_winning_continuations() {
+        // X holds the top-left pair, O holds the centre and bottom-right; X to move
+        let deps = setup_game(&[
+            ("player0", 0, 0),
+            ("player1", 1, 1),
+            ("player0", 0, 1),
+            ("player1", 2, 2),
+        ]);
+
+        let res: WinningContinuationsResponse = query_json(
+            deps.as_ref(),
+            QueryMsg::WinningContinuations {
+                player: Addr::unchecked("player0"),
+            },
+        );
+        // completing the top row wins at once; either left-column cell forks the top row and
+        // the left column, which O cannot block both of
+        assert_eq!(
+            vec![
+                Coord { row: 0, col: 2 },
+                Coord { row: 1, col: 0 },
+                Coord { row: 2, col: 0 },
+            ],
+            res.moves
+        );
+
+        // it is not O's turn, so O has nothing to continue with
+        let res: WinningContinuationsResponse = query_json(
+            deps.as_ref(),
+            QueryMsg::WinningContinuations {
+                player: Addr::unchecked("player1"),
+            },
+        );
+        assert!(res.moves.is_empty());
+
+        let err = query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::WinningContinuations {
+                player: Addr::unchecked("stranger"),
+            },
+        )
+        .unwrap_err();
+        assert_eq!(StdError::generic_err("Not a player in this game"), err);
+    }
+
+    #[test]
+    fn test_stale_nonce_rejected() {
+        let mut deps = setup_game(&[]);
+
+        let msg = ExecuteMsg::Move {
+            row: 0,
+            col: 0,
+            expected_nonce: Some(0),
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("player0", &[]), msg).unwrap();
+        assert!(res
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "nonce" && attr.value == "1"));
+
+        // player1 submits against the state from before player0's move
+        let msg = ExecuteMsg::Move {
+            row: 1,
+            col: 1,
+            expected_nonce: Some(0),
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("player1", &[]), msg).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::StaleNonce {
+                expected: 0,
+                current: 1
+            }
+        ));
+
+        let msg = ExecuteMsg::Move {
+            row: 1,
+            col: 1,
+            expected_nonce: Some(1),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("player1", &[]), msg).unwrap();
+
+        let state: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        assert_eq!(2, state.state.nonce);
+    }
+
+    #[test]
+    fn test_control() {
+        let deps = setup_game(&[
+            ("player0", 0, 0),
+            ("player1", 0, 1),
+            ("player0", 1, 1),
+            ("player1", 2, 2),
+        ]);
+
+        let res: ControlResponse = query_json(deps.as_ref(), QueryMsg::Control {});
+        assert_eq!(2, res.x.count);
+        assert_eq!(
+            vec![Coord { row: 0, col: 0 }, Coord { row: 1, col: 1 }],
+            res.x.cells
+        );
+        assert_eq!(2, res.o.count);
+        assert_eq!(
+            vec![Coord { row: 0, col: 1 }, Coord { row: 2, col: 2 }],
+            res.o.cells
+        );
+        // the top row, the middle column and the main diagonal hold both symbols
+        assert_eq!(
+            vec![
+                [(0, 0), (0, 1), (0, 2)].map(Coord::from),
+                [(0, 1), (1, 1), (2, 1)].map(Coord::from),
+                [(0, 0), (1, 1), (2, 2)].map(Coord::from),
+            ],
+            res.contested_lines
+        );
+    }
+
+    #[test]
+    fn test_my_result() {
+        let my_result = |deps: Deps, player: &str| -> GameResult {
+            let res: MyResultResponse = query_json(
+                deps,
+                QueryMsg::MyResult {
+                    player: Addr::unchecked(player),
+                },
+            );
+            res.result
+        };
+
+        let mut deps = setup_game(&[
+            ("player0", 0, 0),
+            ("player1", 1, 0),
+            ("player0", 0, 1),
+            ("player1", 1, 1),
+        ]);
+        assert_eq!(GameResult::InProgress, my_result(deps.as_ref(), "player0"));
+
+        let msg = ExecuteMsg::Move {
+            row: 0,
+            col: 2,
+            expected_nonce: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("player0", &[]), msg).unwrap();
+
+        assert_eq!(GameResult::Won, my_result(deps.as_ref(), "player0"));
+        assert_eq!(GameResult::Lost, my_result(deps.as_ref(), "player1"));
+        assert_eq!(GameResult::NotAPlayer, my_result(deps.as_ref(), "stranger"));
+    }
+
+    #[test]
+    fn test_is_winning_move() {
+        let deps = setup_game(&[
+            ("player0", 0, 0),
+            ("player1", 1, 0),
+            ("player0", 0, 1),
+            ("player1", 2, 2),
+        ]);
+        let is_winning_move = |row, col| {
+            query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::IsWinningMove {
+                    player: Addr::unchecked("player0"),
+                    row,
+                    col,
+                },
+            )
+            .map(|res| from_binary::<IsWinningMoveResponse>(&res).unwrap().winning)
+        };
+
+        assert_eq!(Ok(true), is_winning_move(0, 2));
+        assert_eq!(Ok(false), is_winning_move(1, 1));
+        assert_eq!(
+            Err(StdError::generic_err("Cell is already occupied")),
+            is_winning_move(0, 0)
+        );
+        assert_eq!(
+            Err(StdError::generic_err("Cell is out of bounds")),
+            is_winning_move(3, 0)
+        );
+    }
+
+    #[test]
+    fn test_board_filling_move_declares_draw() {
+        // X O X
+        // X O O
+        // O X X
+        let mut deps = setup_game(&[
+            ("player0", 0, 0),
+            ("player1", 0, 1),
+            ("player0", 0, 2),
+            ("player1", 1, 1),
+            ("player0", 2, 1),
+            ("player1", 1, 2),
+            ("player0", 1, 0),
+            ("player1", 2, 0),
+        ]);
+        let state: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        assert_eq!(Turn::Player0, state.state.next_turn);
+
+        let msg = ExecuteMsg::Move {
+            row: 2,
+            col: 2,
+            expected_nonce: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("player0", &[]), msg).unwrap();
+
+        let state: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        assert_eq!(Turn::Ended, state.state.next_turn);
+        assert_eq!(None, state.state.winner);
+        let res: MyResultResponse = query_json(
+            deps.as_ref(),
+            QueryMsg::MyResult {
+                player: Addr::unchecked("player1"),
+            },
+        );
+        assert_eq!(GameResult::Drew, res.result);
+    }
+
+    #[test]
+    fn test_time_usage() {
+        let mut deps = setup_game(&[]);
+
+        let mut env = mock_env();
+        for (player, row, col, elapsed) in [("player0", 0, 0, 10), ("player1", 1, 1, 25)] {
+            env.block.time = env.block.time.plus_seconds(elapsed);
+            let msg = ExecuteMsg::Move {
+                row,
+                col,
+                expected_nonce: None,
+            };
+            execute(deps.as_mut(), env.clone(), mock_info(player, &[]), msg).unwrap();
+        }
+
+        let res: TimeUsageResponse = query_json(deps.as_ref(), QueryMsg::GetTimeUsage {});
+        assert_eq!(10, res.player0_seconds);
+        assert_eq!(25, res.player1_seconds);
+    }
+
+    #[test]
+    fn test_positional_metrics() {
+        let deps = setup_game(&[("player0", 1, 1), ("player1", 0, 0), ("player0", 0, 1)]);
+
+        let res: PositionalMetricsResponse =
+            query_json(deps.as_ref(), QueryMsg::PositionalMetrics {});
+        assert_eq!(
+            PositionalCounts {
+                center: 1,
+                corners: 0,
+                edges: 1,
+            },
+            res.x
+        );
+        assert_eq!(
+            PositionalCounts {
+                center: 0,
+                corners: 1,
+                edges: 0,
+            },
+            res.o
+        );
+    }
+
+    #[test]
+    fn test_distance_to_win() {
+        let deps = setup_game(&[("player0", 0, 0), ("player1", 1, 1), ("player0", 0, 1)]);
+
+        let res: DistanceToWinResponse = query_json(deps.as_ref(), QueryMsg::DistanceToWin {});
+        assert_eq!(Some(1), res.x);
+        assert_eq!(Some(2), res.o);
+
+        // X O X
+        // X O O
+        // O X X
+        let deps = setup_game(&[
+            ("player0", 0, 0),
+            ("player1", 0, 1),
+            ("player0", 0, 2),
+            ("player1", 1, 1),
+            ("player0", 2, 1),
+            ("player1", 1, 2),
+            ("player0", 1, 0),
+            ("player1", 2, 0),
+            ("player0", 2, 2),
+        ]);
+        let res: DistanceToWinResponse = query_json(deps.as_ref(), QueryMsg::DistanceToWin {});
+        assert_eq!(None, res.x);
+        assert_eq!(None, res.o);
+    }
+
+    #[test]
+    fn test_fog_hides_latest_move_from_opponent() {
+        let config = GameConfig {
+            fog: true,
+            ..GameConfig::default()
+        };
+        let mut deps = setup_game_with(config, &[("player0", 0, 0)]);
+        let board = |deps: Deps, viewer: &str| {
+            let res: GetBoardResponse = query_json(
+                deps,
+                QueryMsg::GetBoard {
+                    viewer: Addr::unchecked(viewer),
+                },
+            );
+            res.board
+        };
+
+        assert_eq!(GridCell::X, board(deps.as_ref(), "player0")[0][0]);
+        assert_eq!(GridCell::Empty, board(deps.as_ref(), "player1")[0][0]);
+
+        let msg = ExecuteMsg::Move {
+            row: 1,
+            col: 1,
+            expected_nonce: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("player1", &[]), msg).unwrap();
+
+        // replying reveals X's move to O, while O's own move is now the hidden one
+        let o_view = board(deps.as_ref(), "player1");
+        assert_eq!(GridCell::X, o_view[0][0]);
+        assert_eq!(GridCell::O, o_view[1][1]);
+        let x_view = board(deps.as_ref(), "player0");
+        assert_eq!(GridCell::X, x_view[0][0]);
+        assert_eq!(GridCell::Empty, x_view[1][1]);
+    }
+
+    #[test]
+    fn test_non_forking_moves() {
+        // X on opposite corners around O's centre: an O corner lets X fork, an O edge does not
+        let deps = setup_game(&[("player0", 0, 0), ("player1", 1, 1), ("player0", 2, 2)]);
+
+        let res: NonForkingMovesResponse = query_json(
+            deps.as_ref(),
+            QueryMsg::NonForkingMoves {
+                player: Addr::unchecked("player1"),
+            },
+        );
+        assert_eq!(
+            vec![
+                Coord { row: 0, col: 1 },
+                Coord { row: 1, col: 0 },
+                Coord { row: 1, col: 2 },
+                Coord { row: 2, col: 1 },
+            ],
+            res.moves
+        );
+    }
+
+    #[test]
+    fn test_move_algebraic() {
+        let mut deps = setup_game(&[]);
+
+        let msg = ExecuteMsg::MoveAlgebraic {
+            square: "b2".to_string(),
+            expected_nonce: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("player0", &[]), msg).unwrap();
+        let state: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        assert_eq!(GridCell::X, state.state.board[1][1]);
+
+        for square in ["d1", "a4", "b", "B2", "a10"] {
+            let msg = ExecuteMsg::MoveAlgebraic {
+                square: square.to_string(),
+                expected_nonce: None,
+            };
+            let err =
+                execute(deps.as_mut(), mock_env(), mock_info("player1", &[]), msg).unwrap_err();
+            assert!(
+                matches!(err, ContractError::InvalidSquare { .. }),
+                "{}",
+                square
+            );
+        }
+
+        let msg = ExecuteMsg::MoveAlgebraic {
+            square: "a1".to_string(),
+            expected_nonce: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("player1", &[]), msg).unwrap();
+
+        let rendered: RenderBoardResponse = query_json(deps.as_ref(), QueryMsg::RenderAlgebraic {});
+        assert_eq!(
+            "3    |   |   \n  ---+---+---\n2    | X |   \n  ---+---+---\n1  O |   |   \n   a   b   c",
+            rendered.board
+        );
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let deps = setup_game(&[]);
+        let res: CapabilitiesResponse = query_json(deps.as_ref(), QueryMsg::Capabilities {});
+        assert!(res.execute.contains(&"move".to_string()));
+        assert!(res.query.contains(&"get_state".to_string()));
+        assert!(res.query.contains(&"capabilities".to_string()));
+
+        // the lists must name exactly the variants in the message schemas
+        assert_eq!(variant_names(schema_for!(ExecuteMsg)), sorted(&res.execute));
+        assert_eq!(variant_names(schema_for!(QueryMsg)), sorted(&res.query));
+    }
+
+    fn sorted(names: &[String]) -> Vec<String> {
+        let mut names = names.to_vec();
+        names.sort();
+        names
+    }
+
+    /// Top-level property names of an externally tagged enum schema, sorted.
+    fn variant_names(schema: RootSchema) -> Vec<String> {
+        let mut names: Vec<String> = schema
+            .schema
+            .subschemas
+            .unwrap()
+            .one_of
+            .unwrap()
+            .into_iter()
+            .flat_map(|variant| match variant {
+                Schema::Object(object) => object.object.unwrap().properties.into_keys().collect(),
+                Schema::Bool(_) => vec![],
+            })
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_rejected_move_leaves_state_unchanged() {
+        let mut deps = setup_game(&[("player0", 0, 0)]);
+        let before: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+
+        let rejected = [
+            ("player1", 0, 0), // occupied
+            ("player0", 1, 1), // not player0's turn
+            ("player1", 3, 0), // out of bounds
+            ("stranger", 1, 1),
+        ];
+        for (player, row, col) in rejected {
+            let msg = ExecuteMsg::Move {
+                row,
+                col,
+                expected_nonce: None,
+            };
+            let err = execute(deps.as_mut(), mock_env(), mock_info(player, &[]), msg).unwrap_err();
+            assert!(matches!(err, ContractError::InvalidMove { .. }));
+
+            let after: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+            assert_eq!(before, after);
+        }
+        assert_eq!(Turn::Player1, before.state.next_turn);
+    }
+
+    #[test]
+    fn test_threat_count() {
+        // X forks the left column and the bottom row; O's anti-diagonal is blocked
+        let deps = setup_game(&[
+            ("player0", 0, 0),
+            ("player1", 1, 1),
+            ("player0", 2, 2),
+            ("player1", 0, 2),
+            ("player0", 2, 0),
+        ]);
+
+        let res: ThreatCountResponse = query_json(deps.as_ref(), QueryMsg::ThreatCount {});
+        assert_eq!(2, res.x);
+        assert_eq!(0, res.o);
+    }
+
+    #[test]
+    fn test_toroidal_wrapped_diagonal_wins() {
+        // X plays the broken diagonal (0,1), (1,2), (2,0), which only wraps around the edges
+        let moves = [
+            ("player0", 0, 1),
+            ("player1", 0, 0),
+            ("player0", 1, 2),
+            ("player1", 1, 1),
+            ("player0", 2, 0),
+        ];
+
+        let deps = setup_game(&moves);
+        let state: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        assert_eq!(None, state.state.winner);
+        assert_eq!(Turn::Player1, state.state.next_turn);
+
+        let config = GameConfig {
+            toroidal: true,
+            ..GameConfig::default()
+        };
+        let deps = setup_game_with(config, &moves);
+        let state: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        assert_eq!(Some(Addr::unchecked("player0")), state.state.winner);
+        assert_eq!(Turn::Ended, state.state.next_turn);
+
+        let rendered: RenderBoardResponse =
+            query_json(deps.as_ref(), QueryMsg::RenderBoardHighlighted {});
+        assert_eq!(
+            " O |[X]|   \n---+---+---\n   | O |[X]\n---+---+---\n[X]|   |   ",
+            rendered.board
+        );
+    }
+
+    #[test]
+    fn test_toroidal_analysis_uses_wrapped_lines() {
+        let config = GameConfig {
+            toroidal: true,
+            ..GameConfig::default()
+        };
+        // X threatens the wrapped diagonal at (2,0), O the main diagonal at (2,2)
+        let mut moves = vec![
+            ("player0", 0, 1),
+            ("player1", 0, 0),
+            ("player0", 1, 2),
+            ("player1", 1, 1),
+        ];
+        let deps = setup_game_with(config.clone(), &moves);
+
+        let threats: ThreatCountResponse = query_json(deps.as_ref(), QueryMsg::ThreatCount {});
+        assert_eq!(ThreatCountResponse { x: 1, o: 1 }, threats);
+        let summary: TacticalSummaryResponse =
+            query_json(deps.as_ref(), QueryMsg::TacticalSummary {});
+        assert!(summary.can_win);
+        assert!(!summary.must_block);
+        let grouped: MovesByOutcomeResponse =
+            query_json(deps.as_ref(), QueryMsg::MovesByOutcome {});
+        assert_eq!(vec![Coord { row: 2, col: 0 }], grouped.winning);
+        let res: WinningContinuationsResponse = query_json(
+            deps.as_ref(),
+            QueryMsg::WinningContinuations {
+                player: Addr::unchecked("player0"),
+            },
+        );
+        assert!(res.moves.contains(&Coord { row: 2, col: 0 }));
+
+        // once the wrapped diagonal has won, no analysis suggests playing on
+        moves.push(("player0", 2, 0));
+        let deps = setup_game_with(config, &moves);
+        let res: WinningContinuationsResponse = query_json(
+            deps.as_ref(),
+            QueryMsg::WinningContinuations {
+                player: Addr::unchecked("player1"),
+            },
+        );
+        assert!(res.moves.is_empty());
+        let res: PositionDifficultyResponse =
+            query_json(deps.as_ref(), QueryMsg::PositionDifficulty {});
+        assert_eq!(0, res.difficulty);
+        let res: OptimalResultResponse = query_json(
+            deps.as_ref(),
+            QueryMsg::OptimalResult {
+                player: Addr::unchecked("player0"),
+            },
+        );
+        assert_eq!(OptimalResult::Wins, res.result);
+    }
+
+    #[test]
+    fn test_are_equivalent() {
+        let deps = setup_game(&[]);
+        let (e, x, o) = (GridCell::Empty, GridCell::X, GridCell::O);
+        let are_equivalent = |board_a, board_b| {
+            let res: AreEquivalentResponse =
+                query_json(deps.as_ref(), QueryMsg::AreEquivalent { board_a, board_b });
+            res.equivalent
+        };
+
+        let board = [[x, o, e], [e, x, e], [e, e, e]];
+        // the same position turned a quarter clockwise
+        let rotated = [[e, e, x], [e, x, o], [e, e, e]];
+        assert!(are_equivalent(board, rotated));
+
+        // same pieces, but O sits on a corner instead of an edge
+        let different = [[x, e, o], [e, x, e], [e, e, e]];
+        assert!(!are_equivalent(board, different));
+    }
+
+    #[test]
+    fn test_stage_and_confirm_move() {
+        let config = GameConfig {
+            confirm_moves: true,
+            ..GameConfig::default()
+        };
+        let mut deps = setup_game_with(config, &[]);
+        let run = |deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>, player, msg| {
+            execute(deps.as_mut(), mock_env(), mock_info(player, &[]), msg)
+        };
+
+        // direct moves are refused when confirmation is on
+        let msg = ExecuteMsg::Move {
+            row: 0,
+            col: 0,
+            expected_nonce: None,
+        };
+        run(&mut deps, "player0", msg).unwrap_err();
+
+        run(
+            &mut deps,
+            "player0",
+            ExecuteMsg::StageMove {
+                row: 1,
+                col: 1,
+                expected_nonce: Some(0),
+            },
+        )
+        .unwrap();
+        let state: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        assert_eq!(GridCell::Empty, state.state.board[1][1]);
+        assert_eq!(Some(StagedMove { row: 1, col: 1 }), state.state.staged_move);
+
+        // the opponent can neither confirm nor withdraw someone else's staged move
+        let confirm = |expected_nonce| ExecuteMsg::ConfirmMove { expected_nonce };
+        run(&mut deps, "player1", confirm(None)).unwrap_err();
+        run(&mut deps, "player1", ExecuteMsg::CancelMove {}).unwrap_err();
+
+        // staging bumped the nonce, so a confirmation sent against the old view is stale
+        let err = run(&mut deps, "player0", confirm(Some(0))).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::StaleNonce {
+                expected: 0,
+                current: 1
+            }
+        ));
+        run(&mut deps, "player0", confirm(Some(1))).unwrap();
+        let state: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        assert_eq!(GridCell::X, state.state.board[1][1]);
+        assert_eq!(None, state.state.staged_move);
+        assert_eq!(Turn::Player1, state.state.next_turn);
+    }
+
+    #[test]
+    fn test_stage_and_cancel_move() {
+        let config = GameConfig {
+            confirm_moves: true,
+            ..GameConfig::default()
+        };
+        let mut deps = setup_game_with(config, &[]);
+        let before: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+
+        let msg = ExecuteMsg::StageMove {
+            row: 0,
+            col: 2,
+            expected_nonce: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("player0", &[]), msg).unwrap();
+        let msg = ExecuteMsg::CancelMove {};
+        execute(deps.as_mut(), mock_env(), mock_info("player0", &[]), msg).unwrap();
+
+        let after: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        assert_eq!(before.state.board, after.state.board);
+        assert_eq!(before.state.next_turn, after.state.next_turn);
+        assert_eq!(None, after.state.staged_move);
+
+        let msg = ExecuteMsg::ConfirmMove {
+            expected_nonce: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("player0", &[]), msg).unwrap_err();
+    }
+
+    #[test]
+    fn test_staging_requires_confirm_moves() {
+        let mut deps = setup_game(&[("player0", 1, 1)]);
+        for msg in [
+            ExecuteMsg::StageMove {
+                row: 0,
+                col: 0,
+                expected_nonce: None,
+            },
+            ExecuteMsg::ConfirmMove {
+                expected_nonce: None,
+            },
+            ExecuteMsg::CancelMove {},
+        ] {
+            let err =
+                execute(deps.as_mut(), mock_env(), mock_info("player1", &[]), msg).unwrap_err();
+            assert!(matches!(
+                err,
+                ContractError::InvalidMove { msg } if msg == "Move confirmation is not enabled for this game"
+            ));
+        }
+
+        // nor can a migration smuggle a staged move into such a game
+        let mut state: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        state.state.staged_move = Some(StagedMove { row: 0, col: 0 });
+        let msg = MigrateMsg {
+            state: Some(state.state),
+        };
+        let err = migrate(deps.as_mut(), mock_env(), msg).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidState { .. }));
+    }
+
+    #[test]
+    fn test_board_states() {
+        let deps = setup_game(&[("player0", 0, 0), ("player1", 1, 1), ("player0", 2, 2)]);
+
+        let res: BoardStatesResponse = query_json(deps.as_ref(), QueryMsg::BoardStates {});
+        assert_eq!(4, res.boards.len());
+        assert_eq!([[GridCell::Empty; 3]; 3], res.boards[0]);
+        assert_eq!(GridCell::X, res.boards[1][0][0]);
+        assert_eq!(GridCell::Empty, res.boards[1][1][1]);
+        assert_eq!(GridCell::O, res.boards[2][1][1]);
+
+        let state: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        assert_eq!(state.state.board, res.boards[3]);
+    }
+
+    #[test]
+    fn test_optimal_result() {
+        // an edge reply to a centre opening loses for O
+        let deps = setup_game(&[("player0", 1, 1), ("player1", 0, 1)]);
+        let result_for = |player: &str| {
+            let res: OptimalResultResponse = query_json(
+                deps.as_ref(),
+                QueryMsg::OptimalResult {
+                    player: Addr::unchecked(player),
+                },
+            );
+            res.result
+        };
+        // X is to move, O is waiting; the verdict follows the named player either way
+        assert_eq!(OptimalResult::Wins, result_for("player0"));
+        assert_eq!(OptimalResult::Loses, result_for("player1"));
+
+        let deps = setup_game(&[("player0", 1, 1), ("player1", 0, 0)]);
+        let res: OptimalResultResponse = query_json(
+            deps.as_ref(),
+            QueryMsg::OptimalResult {
+                player: Addr::unchecked("player1"),
+            },
+        );
+        assert_eq!(OptimalResult::Draws, res.result);
+
+        let err = query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::OptimalResult {
+                player: Addr::unchecked("stranger"),
+            },
+        );
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn test_moves_by_outcome() {
+        // X threatens (2,0) down the left column, O threatens (2,1) down the middle one
+        let deps = setup_game(&[
+            ("player0", 0, 0),
+            ("player1", 1, 1),
+            ("player0", 1, 0),
+            ("player1", 0, 1),
+        ]);
+
+        let res: MovesByOutcomeResponse = query_json(deps.as_ref(), QueryMsg::MovesByOutcome {});
+        assert_eq!(vec![Coord { row: 2, col: 0 }], res.winning);
+        assert_eq!(vec![Coord { row: 2, col: 1 }], res.blocking);
+        let other: Vec<Coord> = vec![(0, 2).into(), (1, 2).into(), (2, 2).into()];
+        assert_eq!(other, res.other);
+    }
+
+    #[test]
+    fn test_is_mirror_game() {
+        let deps = setup_game(&[
+            ("player0", 0, 0),
+            ("player1", 2, 2),
+            ("player0", 0, 1),
+            ("player1", 2, 1),
+            ("player0", 1, 2),
+        ]);
+        let res: IsMirrorGameResponse = query_json(deps.as_ref(), QueryMsg::IsMirrorGame {});
+        assert!(res.mirror);
+
+        let deps = setup_game(&[
+            ("player0", 0, 0),
+            ("player1", 2, 2),
+            ("player0", 0, 1),
+            ("player1", 1, 1),
+        ]);
+        let res: IsMirrorGameResponse = query_json(deps.as_ref(), QueryMsg::IsMirrorGame {});
+        assert!(!res.mirror);
+    }
+
+    #[test]
+    fn test_canonical_board() {
+        let deps = setup_game(&[("player0", 0, 0), ("player1", 0, 1)]);
+        let res: CanonicalBoardResponse = query_json(deps.as_ref(), QueryMsg::CanonicalBoard {});
+        // the same position turned a quarter clockwise
+        let rotated = setup_game(&[("player0", 0, 2), ("player1", 1, 2)]);
+        let rotated_res: CanonicalBoardResponse =
+            query_json(rotated.as_ref(), QueryMsg::CanonicalBoard {});
+
+        assert_eq!(res.board, rotated_res.board);
+        assert_ne!(res.transform, rotated_res.transform);
+        let state: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        assert_eq!(res.board, res.transform.apply(&state.state.board));
+    }
+
+    #[test]
+    fn test_get_state_with_moves() {
+        let deps = setup_game(&[("player0", 1, 1), ("player1", 0, 0)]);
+
+        let res: GetStateWithMovesResponse =
+            query_json(deps.as_ref(), QueryMsg::GetStateWithMoves {});
+        assert_eq!(7, res.moves.len());
+        for coord in &res.moves {
+            assert_eq!(
+                GridCell::Empty,
+                res.state.board[coord.row as usize][coord.col as usize]
+            );
+        }
+        assert!(!res.moves.contains(&Coord { row: 1, col: 1 }));
+
+        let deps = setup_game(&[
+            ("player0", 0, 0),
+            ("player1", 1, 0),
+            ("player0", 0, 1),
+            ("player1", 1, 1),
+            ("player0", 0, 2),
+        ]);
+        let res: GetStateWithMovesResponse =
+            query_json(deps.as_ref(), QueryMsg::GetStateWithMoves {});
+        assert_eq!(Turn::Ended, res.state.next_turn);
+        assert!(res.moves.is_empty());
+    }
+
+    #[test]
+    fn test_explain_move() {
+        let explain = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>, player, row, col| {
+            let res: ExplainMoveResponse = query_json(
+                deps.as_ref(),
+                QueryMsg::ExplainMove {
+                    player: Addr::unchecked(player),
+                    row,
+                    col,
+                },
+            );
+            res.reason
+        };
+
+        let deps = setup_game(&[("player0", 1, 1)]);
+        assert_eq!(MoveReason::Ok, explain(&deps, "player1", 0, 0));
+        assert_eq!(MoveReason::NotYourTurn, explain(&deps, "player0", 0, 0));
+        assert_eq!(MoveReason::Occupied, explain(&deps, "player1", 1, 1));
+        assert_eq!(MoveReason::OutOfBounds, explain(&deps, "player1", 3, 0));
+        assert_eq!(MoveReason::NotAPlayer, explain(&deps, "stranger", 0, 0));
+
+        let deps = setup_game(&[
+            ("player0", 0, 0),
+            ("player1", 1, 0),
+            ("player0", 0, 1),
+            ("player1", 1, 1),
+            ("player0", 0, 2),
+        ]);
+        assert_eq!(MoveReason::GameEnded, explain(&deps, "player1", 2, 2));
+
+        // with confirmation on the answer is about staging, which a pending move blocks
+        let config = GameConfig {
+            confirm_moves: true,
+            ..GameConfig::default()
+        };
+        let mut deps = setup_game_with(config, &[]);
+        assert_eq!(MoveReason::Ok, explain(&deps, "player0", 1, 1));
+        let msg = ExecuteMsg::StageMove {
+            row: 1,
+            col: 1,
+            expected_nonce: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("player0", &[]), msg).unwrap();
+        assert_eq!(MoveReason::MoveStaged, explain(&deps, "player0", 0, 0));
+        assert_eq!(MoveReason::NotYourTurn, explain(&deps, "player1", 0, 0));
+        let msg = ExecuteMsg::StageMove {
+            row: 0,
+            col: 0,
+            expected_nonce: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("player0", &[]), msg).unwrap_err();
+    }
+
+    #[test]
+    fn test_emit_full_board() {
+        let mut deps = setup_game(&[("player0", 1, 1)]);
+        let msg = ExecuteMsg::Move {
+            row: 0,
+            col: 2,
+            expected_nonce: None,
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("player1", &[]), msg).unwrap();
+        assert!(res.attributes.iter().all(|attr| attr.key != "board"));
+
+        let config = GameConfig {
+            emit_full_board: true,
+            ..GameConfig::default()
+        };
+        let mut deps = setup_game_with(config, &[("player0", 1, 1)]);
+        let msg = ExecuteMsg::Move {
+            row: 0,
+            col: 2,
+            expected_nonce: None,
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("player1", &[]), msg).unwrap();
+        let attr = res
+            .attributes
+            .iter()
+            .find(|attr| attr.key == "board")
+            .unwrap();
+        assert_eq!("--O-X----", attr.value);
+
+        let state: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        assert_eq!(board_string(&state.state.board), attr.value);
+    }
+
+    #[test]
+    fn test_tactical_summary() {
+        // O threatens the left column and X has no line of its own to finish
+        let deps = setup_game(&[
+            ("player0", 1, 1),
+            ("player1", 0, 0),
+            ("player0", 2, 2),
+            ("player1", 1, 0),
+        ]);
+
+        let res: TacticalSummaryResponse = query_json(deps.as_ref(), QueryMsg::TacticalSummary {});
+        assert!(!res.can_win);
+        assert!(res.must_block);
+        assert!(!res.facing_fork);
+
+        let deps = setup_game(&[]);
+        let res: TacticalSummaryResponse = query_json(deps.as_ref(), QueryMsg::TacticalSummary {});
+        assert_eq!(TacticalSummaryResponse::default(), res);
+    }
+
+    #[test]
+    fn test_board_diff() {
+        let deps = setup_game(&[("player0", 0, 0), ("player1", 1, 1), ("player0", 2, 2)]);
+
+        let res: BoardDiffResponse =
+            query_json(deps.as_ref(), QueryMsg::BoardDiff { from: 1, to: 2 });
+        let change = CellChange {
+            row: 1,
+            col: 1,
+            symbol: GridCell::O,
+        };
+        assert_eq!(vec![change], res.changes);
+
+        let res: BoardDiffResponse =
+            query_json(deps.as_ref(), QueryMsg::BoardDiff { from: 3, to: 3 });
+        assert!(res.changes.is_empty());
+
+        let msg = QueryMsg::BoardDiff { from: 2, to: 4 };
+        assert!(query(deps.as_ref(), mock_env(), msg).is_err());
+        let msg = QueryMsg::BoardDiff { from: 2, to: 1 };
+        assert!(query(deps.as_ref(), mock_env(), msg).is_err());
+    }
+
+    #[test]
+    fn test_reset_board_clears_transient_fields() {
+        let mut state = State {
+            players: [Addr::unchecked("player0"), Addr::unchecked("player1")],
+            board: [[GridCell::X; 3]; 3],
+            next_turn: Turn::Ended,
+            winner: Some(Addr::unchecked("player0")),
+            history: vec![MoveRecord {
+                symbol: GridCell::X,
+                row: 0,
+                col: 0,
+                time: Timestamp::from_seconds(1),
+            }],
+            nonce: 7,
+            created_at: Timestamp::from_seconds(1),
+            staged_move: Some(StagedMove { row: 1, col: 1 }),
+            lives_left: [2, 1],
+        };
+
+        let now = Timestamp::from_seconds(100);
+        state.reset_board(now);
+        let expected = State {
+            players: [Addr::unchecked("player0"), Addr::unchecked("player1")],
+            board: [[GridCell::Empty; 3]; 3],
+            next_turn: Turn::Player0,
+            winner: None,
+            history: vec![],
+            nonce: 8,
+            created_at: now,
+            staged_move: None,
+            lives_left: [2, 1],
+        };
+        assert_eq!(expected, state);
+    }
+
+    #[test]
+    fn test_line_status() {
+        let deps = setup_game(&[
+            ("player0", 0, 0),
+            ("player1", 0, 1),
+            ("player0", 1, 1),
+            ("player1", 2, 0),
+        ]);
+
+        let res: LineStatusResponse = query_json(deps.as_ref(), QueryMsg::LineStatus {});
+        assert_eq!(8, res.lines.len());
+        let state_of = |cells: [(usize, usize); 3]| {
+            let cells: Vec<Coord> = cells.iter().map(|cell| Coord::from(*cell)).collect();
+            res.lines
+                .iter()
+                .find(|line| line.cells == cells)
+                .unwrap()
+                .state
+        };
+        assert_eq!(LineState::Blocked, state_of([(0, 0), (0, 1), (0, 2)]));
+        assert_eq!(LineState::OpenForX, state_of([(0, 0), (1, 1), (2, 2)]));
+        assert_eq!(LineState::OpenForO, state_of([(2, 0), (2, 1), (2, 2)]));
+        assert_eq!(LineState::Open, state_of([(0, 2), (1, 2), (2, 2)]));
+    }
+
+    #[test]
+    fn test_migrate_restores_state() {
+        let mut deps = setup_game(&[("player0", 0, 0), ("player1", 1, 1), ("player0", 0, 1)]);
+        let backup: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        let msg = ExecuteMsg::Move {
+            row: 2,
+            col: 2,
+            expected_nonce: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("player1", &[]), msg).unwrap();
+
+        let msg = MigrateMsg {
+            state: Some(backup.state.clone()),
+        };
+        migrate(deps.as_mut(), mock_env(), msg).unwrap();
+        let restored: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        assert_eq!(backup.state, restored.state);
+        let config = CONFIG.load(deps.as_ref().storage).unwrap();
+        validate_state(&restored.state, &config).unwrap();
+
+        // a board that the history does not account for is refused and nothing is replaced
+        let mut corrupt = backup.state.clone();
+        corrupt.board[2][0] = GridCell::O;
+        let msg = MigrateMsg {
+            state: Some(corrupt),
+        };
+        let err = migrate(deps.as_mut(), mock_env(), msg).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidState { .. }));
+
+        let mut corrupt = backup.state.clone();
+        corrupt.next_turn = Turn::Player0;
+        let msg = MigrateMsg {
+            state: Some(corrupt),
+        };
+        migrate(deps.as_mut(), mock_env(), msg).unwrap_err();
+
+        let after: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        assert_eq!(backup.state, after.state);
+    }
+
+    #[test]
+    fn test_migrate_from_first_release() {
+        // the first release stored a bare four-field state and no config, placed O for player0
+        // and X for player1, and left a won game open; this one has player0's opening move
+        let legacy = r#"{"players":["player0","player1"],"board":[["O","Empty","Empty"],["Empty","Empty","Empty"],["Empty","Empty","Empty"]],"next_turn":"Player1","winner":null}"#;
+        let mut deps = first_release_game(legacy);
+        migrate(deps.as_mut(), mock_env(), MigrateMsg { state: None }).unwrap();
+        assert_eq!(GameConfig::default(), CONFIG.load(&deps.storage).unwrap());
+        let res: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        assert_eq!(GridCell::X, res.state.board[0][0]);
+        assert_eq!(Turn::Player1, res.state.next_turn);
+        let res: ExpectedSymbolResponse = query_json(deps.as_ref(), QueryMsg::ExpectedSymbol {});
+        assert_eq!(GridCell::O, res.symbol);
+
+        let msg = ExecuteMsg::Move {
+            row: 1,
+            col: 1,
+            expected_nonce: Some(0),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("player1", &[]), msg).unwrap();
+        let res: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        assert_eq!(GridCell::O, res.state.board[1][1]);
+
+        // player1 completed the top row, but the first release still gave player0 the turn
+        let legacy = r#"{"players":["player0","player1"],"board":[["X","X","X"],["O","O","Empty"],["Empty","Empty","O"]],"next_turn":"Player0","winner":"player1"}"#;
+        let mut deps = first_release_game(legacy);
+        migrate(deps.as_mut(), mock_env(), MigrateMsg { state: None }).unwrap();
+        let res: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        assert_eq!([GridCell::O; 3], res.state.board[0]);
+        assert_eq!(Some(Addr::unchecked("player1")), res.state.winner);
+        assert_eq!(Turn::Ended, res.state.next_turn);
+        let msg = ExecuteMsg::Move {
+            row: 1,
+            col: 2,
+            expected_nonce: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("player0", &[]), msg).unwrap_err();
+
+        // player1 overwrote the opening O, leaving a board no alternating game reaches
+        let legacy = r#"{"players":["player0","player1"],"board":[["X","Empty","Empty"],["Empty","Empty","Empty"],["Empty","Empty","Empty"]],"next_turn":"Player0","winner":null}"#;
+        let mut deps = first_release_game(legacy);
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg { state: None }).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidState { .. }));
+    }
+
+    /// Storage as the first release left it after instantiate and some moves.
+    fn first_release_game(state: &str) -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
+        let mut deps = mock_dependencies();
+        set_contract_version(&mut deps.storage, CONTRACT_NAME, "0.1.0").unwrap();
+        deps.storage.set(b"state", state.as_bytes());
+        deps
+    }
+
+    #[test]
+    fn test_migrate_refusals() {
+        let mut deps = setup_game(&[]);
+        set_contract_version(&mut deps.storage, "crates.io:other", "1.0.0").unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg { state: None }).unwrap_err();
+        assert!(matches!(err, ContractError::WrongContract { .. }));
+
+        // a finished game keeps its result, even against an otherwise valid backup
+        let mut deps = setup_game(&[("player0", 0, 0)]);
+        let backup: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        for (player, row, col) in [
+            ("player1", 1, 0),
+            ("player0", 0, 1),
+            ("player1", 1, 1),
+            ("player0", 0, 2),
+        ] {
+            let msg = ExecuteMsg::Move {
+                row,
+                col,
+                expected_nonce: None,
+            };
+            execute(deps.as_mut(), mock_env(), mock_info(player, &[]), msg).unwrap();
+        }
+        let msg = MigrateMsg {
+            state: Some(backup.state),
+        };
+        let err = migrate(deps.as_mut(), mock_env(), msg).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidState { .. }));
+        let res: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        assert_eq!(Turn::Ended, res.state.next_turn);
+
+        // a backup cannot hand the seats to someone else
+        let mut deps = setup_game(&[("player0", 0, 0)]);
+        let mut backup: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        backup.state.players = [Addr::unchecked("mallory"), Addr::unchecked("mallory")];
+        let msg = MigrateMsg {
+            state: Some(backup.state.clone()),
+        };
+        let err = migrate(deps.as_mut(), mock_env(), msg).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidState { .. }));
+
+        // but a corrupted game that no longer loads can still be restored
+        let good: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        deps.storage.set(b"state", b"{\"players\":");
+        let msg = MigrateMsg {
+            state: Some(backup.state),
+        };
+        migrate(deps.as_mut(), mock_env(), msg).unwrap_err();
+        let msg = MigrateMsg {
+            state: Some(good.state.clone()),
+        };
+        migrate(deps.as_mut(), mock_env(), msg).unwrap();
+        let res: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        assert_eq!(good.state, res.state);
+    }
+
+    #[test]
+    fn test_has_fork_move() {
+        // X holds the corner and centre; (1,0) threatens both (1,2) and (2,0)
+        let deps = setup_game(&[
+            ("player0", 0, 0),
+            ("player1", 0, 1),
+            ("player0", 1, 1),
+            ("player1", 2, 2),
+        ]);
+        let has_fork = |player: &str| {
+            let res: HasForkMoveResponse = query_json(
+                deps.as_ref(),
+                QueryMsg::HasForkMove {
+                    player: Addr::unchecked(player),
+                },
+            );
+            res.fork
+        };
+        assert!(has_fork("player0"));
+        // O is not on turn
+        assert!(!has_fork("player1"));
+
+        let deps = setup_game(&[("player0", 1, 1)]);
+        let res: HasForkMoveResponse = query_json(
+            deps.as_ref(),
+            QueryMsg::HasForkMove {
+                player: Addr::unchecked("player1"),
+            },
+        );
+        assert!(!res.fork);
+    }
+
+    #[test]
+    fn test_position_difficulty() {
+        // only the centre keeps X's win; the other four moves all lose
+        let deps = setup_game(&[
+            ("player0", 0, 0),
+            ("player1", 0, 1),
+            ("player0", 0, 2),
+            ("player1", 2, 1),
+        ]);
+        let res: PositionDifficultyResponse =
+            query_json(deps.as_ref(), QueryMsg::PositionDifficulty {});
+        assert_eq!(80, res.difficulty);
+
+        // on the empty board every opening move draws
+        let deps = setup_game(&[]);
+        let res: PositionDifficultyResponse =
+            query_json(deps.as_ref(), QueryMsg::PositionDifficulty {});
+        assert_eq!(0, res.difficulty);
+    }
+
+    #[test]
+    fn test_blunder_costs_a_life() {
+        let config = GameConfig {
+            lives: 2,
+            ..GameConfig::default()
+        };
+        // an edge reply to a centre opening turns O's draw into a loss
+        let deps = setup_game_with(config.clone(), &[("player0", 1, 1), ("player1", 0, 1)]);
+        let state: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        assert_eq!([2, 1], state.state.lives_left);
+        assert_eq!([[GridCell::Empty; 3]; 3], state.state.board);
+        assert!(state.state.history.is_empty());
+        assert_eq!(Turn::Player0, state.state.next_turn);
+        assert_eq!(None, state.state.winner);
+
+        // a sound reply costs nothing
+        let deps = setup_game_with(config, &[("player0", 1, 1), ("player1", 0, 0)]);
+        let state: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        assert_eq!([2, 2], state.state.lives_left);
+        assert_eq!(GridCell::O, state.state.board[0][0]);
+
+        let config = GameConfig {
+            lives: 1,
+            ..GameConfig::default()
+        };
+        let mut deps = setup_game_with(config, &[("player0", 1, 1)]);
+        let msg = ExecuteMsg::Move {
+            row: 0,
+            col: 1,
+            expected_nonce: None,
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("player1", &[]), msg).unwrap();
+        assert!(res
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "life_lost" && attr.value == "player1"));
+        let state: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        assert_eq!([1, 0], state.state.lives_left);
+        assert_eq!(Some(Addr::unchecked("player0")), state.state.winner);
+        assert_eq!(Turn::Ended, state.state.next_turn);
+
+        // the board is left mid-game, but no analysis plays on after the game is lost
+        let player = || Addr::unchecked("player0");
+        let res: WinningContinuationsResponse = query_json(
+            deps.as_ref(),
+            QueryMsg::WinningContinuations { player: player() },
+        );
+        assert!(res.moves.is_empty());
+        let res: NonForkingMovesResponse = query_json(
+            deps.as_ref(),
+            QueryMsg::NonForkingMoves { player: player() },
+        );
+        assert!(res.moves.is_empty());
+        let res: PositionDifficultyResponse =
+            query_json(deps.as_ref(), QueryMsg::PositionDifficulty {});
+        assert_eq!(0, res.difficulty);
+        let res: OptimalResultResponse = query_json(
+            deps.as_ref(),
+            QueryMsg::OptimalResult {
+                player: Addr::unchecked("player1"),
+            },
+        );
+        assert_eq!(OptimalResult::Loses, res.result);
+        let res: HasForkMoveResponse =
+            query_json(deps.as_ref(), QueryMsg::HasForkMove { player: player() });
+        assert!(!res.fork);
+    }
+
+    #[test]
+    fn test_get_packed_board() {
+        let deps = setup_game(&[("player0", 0, 0), ("player1", 1, 1), ("player0", 2, 2)]);
+
+        let res: GetPackedBoardResponse = query_json(deps.as_ref(), QueryMsg::GetPackedBoard {});
+        // X in cell 0, O in cell 4, X in cell 8
+        assert_eq!(1 | 2 << 8 | 1 << 16, res.packed);
+
+        let state: GetStateResponse = query_json(deps.as_ref(), QueryMsg::GetState {});
+        assert_eq!(Some(state.state.board), unpack_board(res.packed));
+        assert_eq!(res.packed, pack_board(&unpack_board(res.packed).unwrap()));
+
+        assert_eq!(None, unpack_board(0b11));
+        assert_eq!(None, unpack_board(1 << 18));
+    }
+
+    #[test]
+    fn test_solver_visits_each_position_once() {
+        let mut solver = Solver::new(&lines_for(false));
+        let empty = [[GridCell::Empty; 3]; 3];
+        assert_eq!(Outcome::Draw, solver.solve(&empty, GridCell::X));
+        // far below the ~550k nodes of the unmemoised game tree
+        assert!(solver.positions() <= 765);
+
+        // a second search over the same positions is answered from the table
+        let searched = solver.positions();
+        let board = play(&empty, 1, 1, GridCell::X);
+        assert_eq!(Outcome::Draw, solver.solve(&board, GridCell::O));
+        assert_eq!(searched, solver.positions());
+    }
+
+    #[test]
+    fn test_blunder_check_cost_on_first_move() {
+        // the opening move is the most expensive to judge: both searches start from the top
+        let mut solver = Solver::new(&lines_for(false));
+        let empty = [[GridCell::Empty; 3]; 3];
+        assert!(!is_blunder(&mut solver, &empty, 1, 1, GridCell::X));
+        assert!(solver.positions() <= 765);
+
+        let board = play(&empty, 1, 1, GridCell::X);
+        let mut solver = Solver::new(&lines_for(false));
+        assert!(is_blunder(&mut solver, &board, 0, 1, GridCell::O));
+        assert!(solver.positions() <= 765);
+    }
+
+    /// Instantiates a classic game between player0 and player1 and plays the given moves in order.
+    fn setup_game(moves: &[(&str, u8, u8)]) -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
+        setup_game_with(GameConfig::default(), moves)
+    }
+
+    fn setup_game_with(
+        config: GameConfig,
+        moves: &[(&str, u8, u8)],
+    ) -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            opponent: Addr::unchecked("player1"),
+            config,
+            player0: None,
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("player0", &[]), msg).unwrap();
+
+        for (player, row, col) in moves {
+            let msg = ExecuteMsg::Move {
+                row: *row,
+                col: *col,
+                expected_nonce: None,
+            };
+            execute(deps.as_mut(), mock_env(), mock_info(player, &[]), msg).unwrap();
+        }
+        deps
+    }
+
+    fn query_json<T: DeserializeOwned>(deps: Deps, msg: QueryMsg) -> T {
+        from_binary(&query(deps, mock_env(), msg).unwrap()).unwrap()
     }
 }
@@ -17,4 +17,14 @@ pub enum ContractError {
     InvalidMove { msg: String },
     #[error("Invalid player")]
     InvalidPlayer {},
+    #[error("Stale nonce: expected {expected}, current {current}")]
+    StaleNonce { expected: u64, current: u64 },
+    #[error("Funds sent to a game without a wager would be locked")]
+    UnexpectedFunds {},
+    #[error("Invalid square: {square}")]
+    InvalidSquare { square: String },
+    #[error("Invalid state: {msg}")]
+    InvalidState { msg: String },
+    #[error("Cannot migrate from contract {contract}")]
+    WrongContract { contract: String },
 }
@@ -1,3 +1,4 @@
+pub mod analysis;
 pub mod contract;
 mod error;
 pub mod integration_tests;
@@ -1,18 +1,57 @@
+use crate::analysis::Transform;
+use crate::state::{GameConfig, GridCell, State};
 use cosmwasm_std::Addr;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use crate::state::{GridCell, State};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InstantiateMsg {
     pub opponent: Addr,
+    #[serde(default)]
+    pub config: GameConfig,
+    /// Referee mode: when set, this address plays X against `opponent` and the sender only
+    /// officiates, with Reset as their one action.
+    #[serde(default)]
+    pub player0: Option<Addr>,
+}
+
+/// Optionally carries a known-good game to restore in place of the stored one.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrateMsg {
+    #[serde(default)]
+    pub state: Option<State>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
-    Move { row: u8, col: u8 },
-    MoveRandom {}
+    // expected_nonce, when set, must match the current state nonce or the move is rejected
+    Move {
+        row: u8,
+        col: u8,
+        expected_nonce: Option<u64>,
+    },
+    MoveRandom {
+        expected_nonce: Option<u64>,
+    },
+    // square uses algebraic notation, "a1" (bottom left) to "c3" (top right)
+    MoveAlgebraic {
+        square: String,
+        expected_nonce: Option<u64>,
+    },
+    // Two-phase moves for games with confirm_moves: stage a cell, then confirm or cancel it.
+    // expected_nonce guards staging and confirming the same way it guards Move
+    StageMove {
+        row: u8,
+        col: u8,
+        expected_nonce: Option<u64>,
+    },
+    ConfirmMove {
+        expected_nonce: Option<u64>,
+    },
+    CancelMove {},
+    // Reset lets the referee of a referee-mode game void an unfinished game and start afresh
+    Reset {},
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -20,10 +59,378 @@ pub enum ExecuteMsg {
 pub enum QueryMsg {
     // GetCount returns the current count as a json-encoded number
     GetState {},
+    // RenderBoardHighlighted returns the ASCII board with the winning line bracketed
+    RenderBoardHighlighted {},
+    // MoveSequence returns the moves played so far as a compact string, e.g. "X@0,0 O@1,1"
+    MoveSequence {},
+    // ExpectedSymbol returns the symbol due next according to board parity, for desync checks
+    ExpectedSymbol {},
+    // WinningContinuations returns the player's immediate moves that keep a forced win
+    WinningContinuations {
+        player: Addr,
+    },
+    // Control returns the cells each player holds and the lines both players have entered
+    Control {},
+    // MyResult returns the game's result as seen by the given address
+    MyResult {
+        player: Addr,
+    },
+    // IsWinningMove returns whether the player would win at once by playing the given cell
+    IsWinningMove {
+        player: Addr,
+        row: u8,
+        col: u8,
+    },
+    // GetTimeUsage returns how many seconds each player has spent on their moves
+    GetTimeUsage {},
+    // PositionalMetrics returns how many centre, corner and edge cells each player holds
+    PositionalMetrics {},
+    // DistanceToWin returns how many more symbols each player needs for their closest open line
+    DistanceToWin {},
+    // GetBoard returns the board as the viewer is allowed to see it
+    GetBoard {
+        viewer: Addr,
+    },
+    // NonForkingMoves returns the player's moves that leave the opponent no fork next turn
+    NonForkingMoves {
+        player: Addr,
+    },
+    // RenderAlgebraic returns the ASCII board labelled with files a-c and ranks 1-3
+    RenderAlgebraic {},
+    // Capabilities returns the execute and query variant names this build supports
+    Capabilities {},
+    // ThreatCount returns how many lines each player is one move from completing
+    ThreatCount {},
+    // AreEquivalent returns whether two boards are the same up to rotation and reflection
+    AreEquivalent {
+        board_a: [[GridCell; 3]; 3],
+        board_b: [[GridCell; 3]; 3],
+    },
+    // BoardStates returns the board after every move, starting from the empty board
+    BoardStates {},
+    // OptimalResult returns how the game ends for the player if both sides play perfectly from here
+    OptimalResult {
+        player: Addr,
+    },
+    // MovesByOutcome sorts the side to move's options into immediate wins, blocks of the
+    // opponent's next-turn wins, and everything else
+    MovesByOutcome {},
+    // IsMirrorGame returns whether every O move so far reflected the preceding X move through the centre
+    IsMirrorGame {},
+    // CanonicalBoard returns the board in its smallest symmetric orientation and the transform used
+    CanonicalBoard {},
+    // GetStateWithMoves returns the state together with the cells the side to move may play
+    GetStateWithMoves {},
+    // ExplainMove returns why the player could or could not make the move right now, through
+    // StageMove in games with confirm_moves and through Move otherwise
+    ExplainMove {
+        player: Addr,
+        row: u8,
+        col: u8,
+    },
+    // TacticalSummary returns the immediate tactical picture for the side to move
+    TacticalSummary {},
+    // BoardDiff returns the cells filled between the boards after `from` and after `to` moves
+    BoardDiff {
+        from: u32,
+        to: u32,
+    },
+    // LineStatus returns every winning line and whether it is open (to both, X or O), blocked
+    // by both symbols, or completed
+    LineStatus {},
+    // HasForkMove returns whether the player, on turn, can create two winning threats at once
+    HasForkMove {
+        player: Addr,
+    },
+    // PositionDifficulty returns a 0-100 rating of how easy it is for the side to move to go wrong
+    PositionDifficulty {},
+    // GetPackedBoard returns the board packed two bits per cell into a u32, see state::pack_board
+    GetPackedBoard {},
 }
 
+/// Wire names of every ExecuteMsg variant, reported by the Capabilities query.
+pub const EXECUTE_CAPABILITIES: &[&str] = &[
+    "move",
+    "move_random",
+    "move_algebraic",
+    "stage_move",
+    "confirm_move",
+    "cancel_move",
+    "reset",
+];
+
+/// Wire names of every QueryMsg variant, reported by the Capabilities query.
+pub const QUERY_CAPABILITIES: &[&str] = &[
+    "get_state",
+    "render_board_highlighted",
+    "move_sequence",
+    "expected_symbol",
+    "winning_continuations",
+    "control",
+    "my_result",
+    "is_winning_move",
+    "get_time_usage",
+    "positional_metrics",
+    "distance_to_win",
+    "get_board",
+    "non_forking_moves",
+    "render_algebraic",
+    "capabilities",
+    "threat_count",
+    "are_equivalent",
+    "board_states",
+    "optimal_result",
+    "moves_by_outcome",
+    "is_mirror_game",
+    "canonical_board",
+    "get_state_with_moves",
+    "explain_move",
+    "tactical_summary",
+    "board_diff",
+    "line_status",
+    "has_fork_move",
+    "position_difficulty",
+    "get_packed_board",
+];
+
 // We define a custom struct for each query response
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct GetStateResponse {
     pub state: State,
 }
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RenderBoardResponse {
+    pub board: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MoveSequenceResponse {
+    pub sequence: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ExpectedSymbolResponse {
+    pub symbol: GridCell,
+}
+
+/// A board coordinate, zero-based.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+pub struct Coord {
+    pub row: u8,
+    pub col: u8,
+}
+
+impl From<(usize, usize)> for Coord {
+    fn from((row, col): (usize, usize)) -> Self {
+        Coord {
+            row: row as u8,
+            col: col as u8,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct WinningContinuationsResponse {
+    pub moves: Vec<Coord>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PlayerControl {
+    pub count: u32,
+    pub cells: Vec<Coord>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ControlResponse {
+    pub x: PlayerControl,
+    pub o: PlayerControl,
+    pub contested_lines: Vec<[Coord; 3]>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum GameResult {
+    Won,
+    Lost,
+    Drew,
+    InProgress,
+    NotAPlayer,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MyResultResponse {
+    pub result: GameResult,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct IsWinningMoveResponse {
+    pub winning: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TimeUsageResponse {
+    pub player0_seconds: u64,
+    pub player1_seconds: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct PositionalCounts {
+    pub center: u32,
+    pub corners: u32,
+    pub edges: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PositionalMetricsResponse {
+    pub x: PositionalCounts,
+    pub o: PositionalCounts,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DistanceToWinResponse {
+    pub x: Option<u8>,
+    pub o: Option<u8>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct GetBoardResponse {
+    pub board: [[GridCell; 3]; 3],
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct NonForkingMovesResponse {
+    pub moves: Vec<Coord>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CapabilitiesResponse {
+    pub execute: Vec<String>,
+    pub query: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ThreatCountResponse {
+    pub x: u32,
+    pub o: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AreEquivalentResponse {
+    pub equivalent: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BoardStatesResponse {
+    pub boards: Vec<[[GridCell; 3]; 3]>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OptimalResult {
+    Wins,
+    Draws,
+    Loses,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OptimalResultResponse {
+    pub result: OptimalResult,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MovesByOutcomeResponse {
+    pub winning: Vec<Coord>,
+    pub blocking: Vec<Coord>,
+    pub other: Vec<Coord>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct IsMirrorGameResponse {
+    pub mirror: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CanonicalBoardResponse {
+    pub board: [[GridCell; 3]; 3],
+    pub transform: Transform,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct GetStateWithMovesResponse {
+    pub state: State,
+    pub moves: Vec<Coord>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MoveReason {
+    Ok,
+    NotYourTurn,
+    Occupied,
+    OutOfBounds,
+    GameEnded,
+    NotAPlayer,
+    MoveStaged,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ExplainMoveResponse {
+    pub reason: MoveReason,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct TacticalSummaryResponse {
+    pub can_win: bool,
+    pub must_block: bool,
+    pub can_fork: bool,
+    pub facing_fork: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CellChange {
+    pub row: u8,
+    pub col: u8,
+    pub symbol: GridCell,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BoardDiffResponse {
+    pub changes: Vec<CellChange>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LineState {
+    Open,
+    OpenForX,
+    OpenForO,
+    Blocked,
+    Completed,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LineInfo {
+    pub cells: Vec<Coord>,
+    pub state: LineState,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LineStatusResponse {
+    pub lines: Vec<LineInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HasForkMoveResponse {
+    pub fork: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PositionDifficultyResponse {
+    pub difficulty: u8,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct GetPackedBoardResponse {
+    pub packed: u32,
+}
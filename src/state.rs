@@ -1,8 +1,8 @@
-use std::fmt::Formatter;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::fmt::Formatter;
 
-use cosmwasm_std::Addr;
+use cosmwasm_std::{Addr, Timestamp};
 use cw_storage_plus::Item;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -11,13 +11,65 @@ pub struct State {
     pub board: [[GridCell; 3]; 3],
     pub next_turn: Turn,
     pub winner: Option<Addr>,
+    pub history: Vec<MoveRecord>,
+    /// Incremented by every state-changing execute, so clients can detect stale submissions.
+    pub nonce: u64,
+    pub created_at: Timestamp,
+    /// A move recorded by StageMove and awaiting ConfirmMove or CancelMove.
+    pub staged_move: Option<StagedMove>,
+    /// Lives each player has left in the lives variant, indexed like `players`.
+    #[serde(default)]
+    pub lives_left: [u8; 2],
+}
+
+/// The state as the first release stored it. That release placed O for player0 and X for
+/// player1, and never ended a won game, so migrate converts it rather than loading it as is.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LegacyState {
+    pub players: [Addr; 2],
+    pub board: [[GridCell; 3]; 3],
+    pub next_turn: Turn,
+    pub winner: Option<Addr>,
+}
+
+impl State {
+    /// Starts a fresh board at `now`, clearing every per-game field while keeping the players
+    /// and their remaining lives.
+    /// Any field added to State that belongs to a single game must be reset here too.
+    pub fn reset_board(&mut self, now: Timestamp) {
+        self.board = [[GridCell::Empty; 3]; 3];
+        self.next_turn = Turn::Player0;
+        self.winner = None;
+        self.history.clear();
+        self.staged_move = None;
+        self.created_at = now;
+        // keep counting rather than restart, so submissions against the old board go stale
+        self.nonce += 1;
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StagedMove {
+    pub row: u8,
+    pub col: u8,
+}
+
+/// A single move as it was played, in the order it was played.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MoveRecord {
+    pub symbol: GridCell,
+    pub row: u8,
+    pub col: u8,
+    /// Block time at which the move was played.
+    pub time: Timestamp,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub enum Turn {
     Player0,
     Player1,
-    Ended
+    Ended,
 }
 
 impl ::std::fmt::Display for Turn {
@@ -25,17 +77,93 @@ impl ::std::fmt::Display for Turn {
         let item = match self {
             Turn::Player0 => "X",
             Turn::Player1 => "O",
-            _ => "invalid"
+            _ => "invalid",
         };
         write!(f, "{}", item)
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Copy)]
+/// Ordered Empty < X < O, which is the order canonical boards are compared in.
+#[derive(
+    Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, JsonSchema, Copy,
+)]
 pub enum GridCell {
     Empty,
     X,
     O,
 }
 
+impl ::std::fmt::Display for GridCell {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let item = match self {
+            GridCell::Empty => " ",
+            GridCell::X => "X",
+            GridCell::O => "O",
+        };
+        write!(f, "{}", item)
+    }
+}
+
+/// Packs the board into the low 18 bits of a `u32`, two bits per cell in row-major order
+/// starting from the least significant bits: 0 is empty, 1 is X and 2 is O.
+pub fn pack_board(board: &[[GridCell; 3]; 3]) -> u32 {
+    board
+        .iter()
+        .flatten()
+        .enumerate()
+        .fold(0, |packed, (i, cell)| {
+            let code = match cell {
+                GridCell::Empty => 0,
+                GridCell::X => 1,
+                GridCell::O => 2,
+            };
+            packed | code << (2 * i)
+        })
+}
+
+/// Inverse of `pack_board`; `None` if a cell holds the unused code 3 or any bit above the
+/// ninth cell is set.
+pub fn unpack_board(packed: u32) -> Option<[[GridCell; 3]; 3]> {
+    if packed >> 18 != 0 {
+        return None;
+    }
+    let mut board = [[GridCell::Empty; 3]; 3];
+    for (i, cell) in board.iter_mut().flatten().enumerate() {
+        *cell = match (packed >> (2 * i)) & 0b11 {
+            0 => GridCell::Empty,
+            1 => GridCell::X,
+            2 => GridCell::O,
+            _ => return None,
+        };
+    }
+    Some(board)
+}
+
+/// Optional rule variants chosen at instantiate. Everything defaults to the classic game.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct GameConfig {
+    /// Hide each move from the opponent in GetBoard until they have replied. This only filters
+    /// GetBoard; the raw state stays readable.
+    #[serde(default)]
+    pub fog: bool,
+    /// Let diagonals wrap around the board edges, as if it were drawn on a torus. Move
+    /// resolution, the renders and every analysis query count the wrapped lines as wins.
+    #[serde(default)]
+    pub toroidal: bool,
+    /// Require every move to be staged and then confirmed, guarding against mis-clicks.
+    #[serde(default)]
+    pub confirm_moves: bool,
+    /// Add the whole board as a `board` attribute to every move response.
+    #[serde(default)]
+    pub emit_full_board: bool,
+    /// Lives per player: a blunder, judged by the solver, costs a life and restarts the
+    /// board instead of playing on, and losing the last life loses the game. 0 turns this off.
+    #[serde(default)]
+    pub lives: u8,
+}
+
 pub const STATE: Item<State> = Item::new("state");
+pub const LEGACY_STATE: Item<LegacyState> = Item::new("state");
+pub const CONFIG: Item<GameConfig> = Item::new("config");
+/// The officiating address of a referee-mode game; absent when the sender plays.
+pub const REFEREE: Item<Addr> = Item::new("referee");